@@ -19,6 +19,11 @@ const SHOULD_BUILD_DB: bool = true;
 #[cfg(not(feature = "db"))]
 const SHOULD_BUILD_DB: bool = false;
 
+#[cfg(feature = "postgres")]
+const MIGRATIONS_DIR: &str = "migrations/postgres";
+#[cfg(not(feature = "postgres"))]
+const MIGRATIONS_DIR: &str = "migrations/sqlite";
+
 fn main() {
     if SHOULD_BUILD_DB {
         create_db();
@@ -112,6 +117,8 @@ fn create_db() {
     Command::new("sqlx")
         .arg("migrate")
         .arg("run")
+        .arg("--source")
+        .arg(MIGRATIONS_DIR)
         .status()
         .unwrap_or_else(|_| {
             exit(1);
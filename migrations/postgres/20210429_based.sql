@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS contents
+(
+    id          BIGSERIAL PRIMARY KEY NOT NULL,
+    chat_id     BIGINT                NOT NULL,
+    is_image    BOOLEAN               NOT NULL,
+    name        TEXT                  NOT NULL,
+    words       TEXT                  NOT NULL,
+    data        BYTEA                 NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS chat_settings
+(
+    chat_id     BIGINT PRIMARY KEY NOT NULL,
+    attribution BOOLEAN            NOT NULL
+);
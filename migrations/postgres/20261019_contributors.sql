@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS contributors
+(
+    chat_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    name    TEXT   NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
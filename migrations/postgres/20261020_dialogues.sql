@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS dialogues
+(
+    chat_id BIGINT PRIMARY KEY NOT NULL,
+    state   TEXT               NOT NULL
+);
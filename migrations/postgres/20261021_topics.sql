@@ -0,0 +1,5 @@
+ALTER TABLE contents ADD COLUMN thread_id BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE chat_settings ADD COLUMN thread_id BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE chat_settings DROP CONSTRAINT chat_settings_pkey;
+ALTER TABLE chat_settings ADD PRIMARY KEY (chat_id, thread_id);
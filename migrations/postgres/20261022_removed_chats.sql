@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS removed_chats
+(
+    chat_id    BIGINT PRIMARY KEY NOT NULL,
+    removed_at BIGINT             NOT NULL
+);
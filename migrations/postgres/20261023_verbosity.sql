@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN verbosity TEXT NOT NULL DEFAULT 'brief';
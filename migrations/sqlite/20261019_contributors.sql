@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS contributors
+(
+    chat_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    name    TEXT    NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
@@ -0,0 +1,13 @@
+ALTER TABLE contents ADD COLUMN thread_id INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS chat_settings_new
+(
+    chat_id     INTEGER NOT NULL,
+    thread_id   INTEGER NOT NULL,
+    attribution BOOLEAN NOT NULL,
+    PRIMARY KEY (chat_id, thread_id)
+);
+INSERT INTO chat_settings_new (chat_id, thread_id, attribution)
+SELECT chat_id, 0, attribution FROM chat_settings;
+DROP TABLE chat_settings;
+ALTER TABLE chat_settings_new RENAME TO chat_settings;
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN triggers BOOLEAN NOT NULL DEFAULT TRUE;
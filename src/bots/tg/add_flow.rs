@@ -0,0 +1,421 @@
+//! Step by step content adding
+//!
+//! `/addimage` or `/addaudio` without trigger words starts a flow: bot asks for media unless it
+//! is attached to or replied by the command, then for trigger words, and sends a copy of the media
+//! with its name and words as preview with buttons to save or cancel. Flow state is a teloxide
+//! dialogue stored in the bot database, so flows survive restarts. Dialogues are per chat, so a
+//! chat has one flow at a time: only messages of the user who started it are taken, other users
+//! can't start own flow until it is saved or cancelled. `/cancel` stops it, chat admins can
+//! cancel flows of other users, e.g. abandoned ones.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use teloxide::dispatching::dialogue::{self, Dialogue, Storage};
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, User};
+use teloxide::{Bot, RequestError};
+
+use super::media::{find_media, media_error_text, store_media, MediaError, MediaFile};
+use super::permission::{get_role, get_user_role, Role};
+use super::{library_thread, topic_id, WORDS_REGEX};
+use crate::models::content_model::{ContentKind, ContentModel};
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use crate::utils::locale::TEXTS;
+
+const SAVE_DATA: &str = "flow:save";
+const CANCEL_DATA: &str = "flow:cancel";
+
+type AddDialogue = Dialogue<AddFlow, DBConn>;
+
+/// State of content adding flow in chat
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum AddFlow {
+    #[default]
+    Idle,
+    ReceiveMedia {
+        user_id: UserId,
+        kind: ContentKind,
+    },
+    ReceiveWords {
+        user_id: UserId,
+        kind: ContentKind,
+        media: MediaFile,
+    },
+    Confirm {
+        user_id: UserId,
+        kind: ContentKind,
+        media: MediaFile,
+        words: String,
+    },
+}
+
+impl AddFlow {
+    /// User who started the flow, `None` if there is no flow
+    fn user_id(&self) -> Option<UserId> {
+        match self {
+            AddFlow::Idle => None,
+            AddFlow::ReceiveMedia { user_id, .. }
+            | AddFlow::ReceiveWords { user_id, .. }
+            | AddFlow::Confirm { user_id, .. } => Some(*user_id),
+        }
+    }
+}
+
+/// Dialogues are kept in `dialogues` table as JSON
+impl<D> Storage<D> for DBConn
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = HandlerError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move { self.rm_dialogue_state(chat_id.0).await })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_string(&dialogue)?;
+            self.set_dialogue_state(chat_id.0, state).await
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<D>, Self::Error>> + Send>> {
+        Box::pin(async move {
+            match self.get_dialogue_state(chat_id.0).await? {
+                Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// Messages of users in the middle of flow, needs `Arc<DBConn>` dependency
+pub(super) fn message_handler() -> UpdateHandler<RequestError> {
+    dialogue::enter::<Message, DBConn, AddFlow, _>()
+        .filter(|message: Message, state: AddFlow| {
+            state.user_id().is_some() && state.user_id() == message.from().map(|user| user.id)
+        })
+        .endpoint(flow_message_handler)
+}
+
+/// Preview buttons pressed by user who started the flow, needs `Arc<DBConn>` dependency
+pub(super) fn callback_handler() -> UpdateHandler<RequestError> {
+    dialogue::enter::<CallbackQuery, DBConn, AddFlow, _>()
+        .filter(|query: CallbackQuery, state: AddFlow| {
+            matches!(query.data.as_deref(), Some(SAVE_DATA) | Some(CANCEL_DATA))
+                && state.user_id() == Some(query.from.id)
+        })
+        .endpoint(flow_callback_handler)
+}
+
+/// Start adding content of kind, media attached to command message is taken right away
+///
+/// Parameters:
+///  - bot: bot instance
+///  - msg: `/addimage` or `/addaudio` command message
+///  - db_conn: database connection to store flow state
+///  - kind: kind of content to add
+pub(super) async fn start_flow(
+    bot: &Bot,
+    msg: &Message,
+    db_conn: &DBConn,
+    kind: ContentKind,
+) -> Result<(), HandlerError> {
+    // Flow takes messages of its user, channel posts have none
+    let user_id = match msg.from() {
+        Some(user) => user.id,
+        None => {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("channel_unsupported", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+    let dialogue = AddDialogue::new(Arc::new(db_conn.clone()), msg.chat.id);
+    let started_by = dialogue.get().await?.unwrap_or_default().user_id();
+    if started_by.is_some() && started_by != Some(user_id) {
+        bot.send_message(msg.chat.id, TEXTS.get_tg("flow_busy", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    let (state, key) = match find_media(msg, kind) {
+        Some(media) => (
+            AddFlow::ReceiveWords {
+                user_id,
+                kind,
+                media,
+            },
+            "flow_ask_words",
+        ),
+        None => (AddFlow::ReceiveMedia { user_id, kind }, ask_media_key(kind)),
+    };
+    info!("Start adding {:?} in chat {}.", kind, msg.chat.id);
+    dialogue.update(state).await?;
+    bot.send_message(msg.chat.id, TEXTS.get_tg(key, msg))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Stop flow in message chat, flows of other users are stopped only by chat admins
+pub(super) async fn cancel_flow(
+    bot: &Bot,
+    msg: &Message,
+    db_conn: &DBConn,
+) -> Result<(), HandlerError> {
+    let dialogue = AddDialogue::new(Arc::new(db_conn.clone()), msg.chat.id);
+    let started_by = dialogue.get().await?.unwrap_or_default().user_id();
+    let can_cancel = match started_by {
+        Some(user_id) if msg.from().map(|user| user.id) == Some(user_id) => true,
+        Some(_) => get_role(bot, msg, Some(db_conn)).await >= Role::Admin,
+        None => false,
+    };
+    let key = if can_cancel {
+        dialogue.exit().await?;
+        "flow_cancelled"
+    } else {
+        "flow_nothing_to_cancel"
+    };
+    bot.send_message(msg.chat.id, TEXTS.get_tg(key, msg))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+fn ask_media_key(kind: ContentKind) -> &'static str {
+    match kind {
+        ContentKind::Image => "flow_ask_image",
+        ContentKind::Audio => "flow_ask_audio",
+    }
+}
+
+async fn flow_message_handler(
+    bot: Bot,
+    message: Message,
+    dialogue: AddDialogue,
+    state: AddFlow,
+) -> ResponseResult<()> {
+    if let Err(e) = handle_flow_message(&bot, &message, &dialogue, state).await {
+        error!("{:?}", e);
+    }
+    Ok(())
+}
+
+async fn handle_flow_message(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &AddDialogue,
+    state: AddFlow,
+) -> Result<(), HandlerError> {
+    match state {
+        AddFlow::Idle => {}
+        AddFlow::ReceiveMedia { user_id, kind } => match find_media(msg, kind) {
+            Some(media) => {
+                dialogue
+                    .update(AddFlow::ReceiveWords {
+                        user_id,
+                        kind,
+                        media,
+                    })
+                    .await?;
+                bot.send_message(msg.chat.id, TEXTS.get_tg("flow_ask_words", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+            }
+            None => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg(ask_media_key(kind), msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+            }
+        },
+        AddFlow::ReceiveWords {
+            user_id,
+            kind,
+            media,
+        } => {
+            let words = msg.text().unwrap_or("").trim();
+            if !WORDS_REGEX.is_match(words) {
+                bot.send_message(msg.chat.id, TEXTS.get_tg("keyword_error", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Ok(());
+            }
+            let preview = ContentModel::from(
+                msg.chat.id.0,
+                kind.is_image(),
+                String::from(words),
+                media.file_name.clone(),
+                Vec::new(),
+            );
+            send_preview(bot, msg, &media, &preview).await?;
+            dialogue
+                .update(AddFlow::Confirm {
+                    user_id,
+                    kind,
+                    media,
+                    words: preview.words,
+                })
+                .await?;
+        }
+        AddFlow::Confirm { .. } => {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("flow_press_button", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Send copy of media captioned with its name and words, with buttons to save or cancel
+///
+/// Media which can't be captioned, e.g. stickers, is previewed by text reply to it.
+async fn send_preview(
+    bot: &Bot,
+    msg: &Message,
+    media: &MediaFile,
+    preview: &ContentModel,
+) -> Result<(), HandlerError> {
+    let caption = format!("{} - {}", preview.name, preview.words);
+    let mut request = bot
+        .copy_message(msg.chat.id, msg.chat.id, media.message_id)
+        .caption(caption.as_str())
+        .reply_markup(confirm_keyboard(msg.from()))
+        .reply_to_message_id(media.message_id);
+    request.message_thread_id = topic_id(msg);
+    if let Err(e) = request.await {
+        warn!("Media can't be copied for preview: {:?}", e);
+        let mut request = bot
+            .send_message(msg.chat.id, caption)
+            .reply_markup(confirm_keyboard(msg.from()))
+            .reply_to_message_id(media.message_id);
+        request.message_thread_id = topic_id(msg);
+        request.await?;
+    }
+    Ok(())
+}
+
+fn confirm_keyboard(user: Option<&User>) -> InlineKeyboardMarkup {
+    let lang = user
+        .and_then(|user| user.language_code.as_deref())
+        .unwrap_or("en");
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(TEXTS.get("tg_flow_save", lang), SAVE_DATA),
+        InlineKeyboardButton::callback(TEXTS.get("tg_flow_cancel", lang), CANCEL_DATA),
+    ]])
+}
+
+async fn flow_callback_handler(
+    bot: Bot,
+    query: CallbackQuery,
+    dialogue: AddDialogue,
+    state: AddFlow,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    if let Err(e) = handle_flow_callback(&bot, &query, &dialogue, state, db_conn.as_ref()).await {
+        error!("{:?}", e);
+    }
+    Ok(())
+}
+
+async fn handle_flow_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    dialogue: &AddDialogue,
+    state: AddFlow,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let db_conn = db_conn.ok_or(HandlerError::Config(
+        "DB connection is not available".into(),
+    ))?;
+    let message = query.message.as_ref().ok_or(HandlerError::Telegram(
+        "Preview message is not available".into(),
+    ))?;
+    let lang = query.from.language_code.as_deref().unwrap_or("en");
+    let (kind, media, words) = match state {
+        AddFlow::Confirm {
+            kind, media, words, ..
+        } if query.data.as_deref() == Some(SAVE_DATA) => (kind, media, words),
+        AddFlow::Confirm { .. } => {
+            dialogue.exit().await?;
+            bot.answer_callback_query(&query.id)
+                .text(TEXTS.get("tg_flow_cancelled", lang))
+                .await?;
+            bot.edit_message_reply_markup(message.chat.id, message.id)
+                .await?;
+            return Ok(());
+        }
+        _ => {
+            bot.answer_callback_query(&query.id).await?;
+            return Ok(());
+        }
+    };
+    // User could be demoted since the flow was started
+    if get_user_role(bot, &message.chat, &query.from, Some(db_conn)).await < Role::Contributor {
+        dialogue.exit().await?;
+        bot.answer_callback_query(&query.id)
+            .text(TEXTS.get("tg_not_permitted", lang))
+            .show_alert(true)
+            .await?;
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+        return Ok(());
+    }
+    let thread_id = library_thread(message);
+    match store_media(
+        bot,
+        db_conn,
+        message.chat.id,
+        thread_id,
+        words,
+        &media,
+        kind,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(MediaError::Db(err)) => return Err(err),
+        Err(err) => {
+            bot.answer_callback_query(&query.id)
+                .text(media_error_text(&err, kind, lang))
+                .show_alert(true)
+                .await?;
+            return Err(err.into());
+        }
+    }
+    dialogue.exit().await?;
+    let key = match kind {
+        ContentKind::Image => "tg_image_add_success",
+        ContentKind::Audio => "tg_audio_add_success",
+    };
+    bot.answer_callback_query(&query.id).await?;
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .await?;
+    bot.send_message(message.chat.id, TEXTS.get(key, lang))
+        .reply_to_message_id(message.id)
+        .await?;
+    Ok(())
+}
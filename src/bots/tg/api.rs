@@ -0,0 +1,65 @@
+//! Telegram Bot API server
+//!
+//! Bot talks to api.telegram.org unless `TELEGRAM_API_URL` points to a self-hosted
+//! `telegram-bot-api` server or a mock server in tests. Server started with `--local` returns
+//! absolute paths of files on its disk instead of download paths, set `TELEGRAM_API_LOCAL`
+//! to read them directly, the bot needs access to the server files directory.
+
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use reqwest::Url;
+use teloxide::Bot;
+
+const TELEGRAM_API_URL_KEY: &str = "TELEGRAM_API_URL";
+const TELEGRAM_API_LOCAL_KEY: &str = "TELEGRAM_API_LOCAL";
+
+lazy_static! {
+    static ref TELEGRAM_API_URL: Option<Url> = std::env::var(TELEGRAM_API_URL_KEY)
+        .ok()
+        .filter(|i| !i.is_empty())
+        .and_then(|i| match i.parse() {
+            Ok(url) => Some(url),
+            Err(e) => {
+                error!("Invalid {}: {:?}", TELEGRAM_API_URL_KEY, e);
+                None
+            }
+        });
+    static ref TELEGRAM_API_LOCAL: bool = std::env::var(TELEGRAM_API_LOCAL_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(false);
+}
+
+/// Create bot with token from env talking to configured API server
+pub(super) fn create_bot() -> Bot {
+    let bot = Bot::from_env();
+    match TELEGRAM_API_URL.clone() {
+        Some(url) => {
+            info!("Using Bot API server {}.", url);
+            bot.set_api_url(url)
+        }
+        None => bot,
+    }
+}
+
+/// Read file from disk of local Bot API server
+///
+/// Parameters:
+///  - path: file path returned by `getFile`
+///
+/// Return: file content or `None` if bot is not in local mode or path is not absolute,
+/// file should be downloaded then
+pub(super) fn read_local_file(path: &str) -> Option<Vec<u8>> {
+    if !*TELEGRAM_API_LOCAL || !Path::new(path).is_absolute() {
+        return None;
+    }
+    match std::fs::read(path) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            error!("Failed to read local file {}: {:?}", path, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,500 @@
+//! Content browser
+//!
+//! `/listimage` and `/listaudio` reply with a browser message showing one chat content item
+//! with inline keyboard to page through others, delete the item, edit its trigger words and
+//! test-render a meme with it. Button presses come back as callback queries with
+//! `BrowserAction` in data, words edit waits for a reply to a force-reply prompt.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use teloxide::prelude::*;
+use teloxide::types::{
+    ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaAudio,
+    InputMediaPhoto, MessageId, User,
+};
+use teloxide::Bot;
+
+use super::permission::{get_role, get_user_role, Role};
+use super::{library_thread, send_v_data, WORDS_REGEX};
+use crate::engine::engine::render_message;
+use crate::models::content_model::{ContentKind, ContentModel};
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use crate::models::template::Template;
+use crate::utils::locale::TEXTS;
+use crate::utils::shutdown::Job;
+
+const ACTION_PREFIX: &str = "br";
+const EDIT_PROMPTS_SIZE: usize = 1000;
+
+lazy_static! {
+    /// Words edit prompts waiting for reply: prompt message to content id and prompted user
+    static ref EDIT_PROMPTS: RwLock<HashMap<(ChatId, MessageId), (i64, UserId)>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Button of browser message, serialized into callback data
+#[derive(Debug, Clone, PartialEq)]
+enum BrowserAction {
+    /// Show item at page of chat contents of kind
+    Show {
+        kind: ContentKind,
+        page: i64,
+    },
+    /// Ask to confirm removal of item shown at page
+    AskDelete {
+        kind: ContentKind,
+        page: i64,
+        id: i64,
+    },
+    Delete {
+        kind: ContentKind,
+        page: i64,
+        id: i64,
+    },
+    Edit {
+        id: i64,
+    },
+    Test {
+        id: i64,
+    },
+}
+
+impl BrowserAction {
+    fn to_data(&self) -> String {
+        match self {
+            BrowserAction::Show { kind, page } => {
+                format!("{}:show:{}:{}", ACTION_PREFIX, kind_name(*kind), page)
+            }
+            BrowserAction::AskDelete { kind, page, id } => {
+                format!("{}:ask:{}:{}:{}", ACTION_PREFIX, kind_name(*kind), page, id)
+            }
+            BrowserAction::Delete { kind, page, id } => {
+                format!("{}:del:{}:{}:{}", ACTION_PREFIX, kind_name(*kind), page, id)
+            }
+            BrowserAction::Edit { id } => format!("{}:edit:{}", ACTION_PREFIX, id),
+            BrowserAction::Test { id } => format!("{}:test:{}", ACTION_PREFIX, id),
+        }
+    }
+
+    fn parse(data: &str) -> Option<Self> {
+        let parts: Vec<&str> = data.split(':').collect();
+        let kind = |i: usize| match parts.get(i) {
+            Some(&"image") => Some(ContentKind::Image),
+            Some(&"audio") => Some(ContentKind::Audio),
+            _ => None,
+        };
+        let num = |i: usize| parts.get(i).and_then(|i| i.parse::<i64>().ok());
+        if parts.first() != Some(&ACTION_PREFIX) {
+            return None;
+        }
+        match parts.get(1) {
+            Some(&"show") => Some(BrowserAction::Show {
+                kind: kind(2)?,
+                page: num(3)?,
+            }),
+            Some(&"ask") => Some(BrowserAction::AskDelete {
+                kind: kind(2)?,
+                page: num(3)?,
+                id: num(4)?,
+            }),
+            Some(&"del") => Some(BrowserAction::Delete {
+                kind: kind(2)?,
+                page: num(3)?,
+                id: num(4)?,
+            }),
+            Some(&"edit") => Some(BrowserAction::Edit { id: num(2)? }),
+            Some(&"test") => Some(BrowserAction::Test { id: num(2)? }),
+            _ => None,
+        }
+    }
+
+    /// Minimal role of user allowed to press the button
+    fn role(&self) -> Role {
+        match self {
+            BrowserAction::Show { .. } | BrowserAction::Test { .. } => Role::Member,
+            BrowserAction::AskDelete { .. }
+            | BrowserAction::Delete { .. }
+            | BrowserAction::Edit { .. } => Role::Admin,
+        }
+    }
+}
+
+fn kind_name(kind: ContentKind) -> &'static str {
+    match kind {
+        ContentKind::Image => "image",
+        ContentKind::Audio => "audio",
+    }
+}
+
+fn button(text: String, action: BrowserAction) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, action.to_data())
+}
+
+/// Get localized button or notification text for user pressed the button
+fn get_browse_text(key: &str, user: &User) -> String {
+    let lang = user.language_code.as_deref().unwrap_or("en");
+    TEXTS.get(&format!("tg_browse_{}", key), lang)
+}
+
+fn caption(item: &ContentModel, page: i64, count: i64) -> String {
+    format!("{} - {}\n{}/{}", item.name, item.words, page + 1, count)
+}
+
+fn keyboard(item: &ContentModel, page: i64, count: i64, user: &User) -> InlineKeyboardMarkup {
+    let kind = ContentKind::from_is_image(item.is_image);
+    let mut rows = Vec::new();
+    if count > 1 {
+        rows.push(vec![
+            button(
+                String::from("◀️"),
+                BrowserAction::Show {
+                    kind,
+                    page: (page + count - 1) % count,
+                },
+            ),
+            button(
+                String::from("▶️"),
+                BrowserAction::Show {
+                    kind,
+                    page: (page + 1) % count,
+                },
+            ),
+        ]);
+    }
+    rows.push(vec![
+        button(
+            get_browse_text("delete", user),
+            BrowserAction::AskDelete {
+                kind,
+                page,
+                id: item.id,
+            },
+        ),
+        button(
+            get_browse_text("edit", user),
+            BrowserAction::Edit { id: item.id },
+        ),
+        button(
+            get_browse_text("test", user),
+            BrowserAction::Test { id: item.id },
+        ),
+    ]);
+    InlineKeyboardMarkup::new(rows)
+}
+
+fn confirm_keyboard(kind: ContentKind, page: i64, id: i64, user: &User) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        button(
+            get_browse_text("confirm", user),
+            BrowserAction::Delete { kind, page, id },
+        ),
+        button(
+            get_browse_text("back", user),
+            BrowserAction::Show { kind, page },
+        ),
+    ]])
+}
+
+fn media_file(item: &ContentModel) -> InputFile {
+    InputFile::memory(item.data.clone()).file_name(item.name.clone())
+}
+
+/// Get item at page of library of message chat topic, page is wrapped around if contents
+/// were removed since it was shown
+///
+/// Return: item with its page and number of items, `None` if there are no items of kind
+async fn get_page(
+    db_conn: &DBConn,
+    message: &Message,
+    kind: ContentKind,
+    page: i64,
+) -> Result<Option<(ContentModel, i64, i64)>, HandlerError> {
+    let (chat_id, thread_id) = (message.chat.id.0, library_thread(message));
+    let count = db_conn
+        .count_contents(chat_id, thread_id, kind.is_image())
+        .await?;
+    if count == 0 {
+        return Ok(None);
+    }
+    let page = page.rem_euclid(count);
+    Ok(db_conn
+        .get_content_at(chat_id, thread_id, kind.is_image(), page)
+        .await?
+        .map(|item| (item, page, count)))
+}
+
+/// Reply to message with browser of chat contents of kind
+///
+/// Parameters:
+///  - bot: bot instance
+///  - msg: `/listimage` or `/listaudio` command message
+///  - db_conn: database connection
+///  - kind: kind of contents to browse
+pub(super) async fn send_browser(
+    bot: &Bot,
+    msg: &Message,
+    db_conn: &DBConn,
+    kind: ContentKind,
+) -> Result<(), HandlerError> {
+    // Buttons and edit prompts need the user, channel posts have none
+    let user = match msg.from() {
+        Some(user) => user,
+        None => {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("channel_unsupported", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+    let (item, page, count) = match get_page(db_conn, msg, kind, 0).await? {
+        Some(page) => page,
+        None => {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("empty_list_message", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+    let markup = keyboard(&item, page, count, user);
+    match kind {
+        ContentKind::Image => {
+            bot.send_photo(msg.chat.id, media_file(&item))
+                .caption(caption(&item, page, count))
+                .reply_markup(markup)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        ContentKind::Audio => {
+            bot.send_audio(msg.chat.id, media_file(&item))
+                .caption(caption(&item, page, count))
+                .reply_markup(markup)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub(super) async fn browser_handler(
+    bot: Bot,
+    query: CallbackQuery,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    // Test button renders a meme, shutdown waits for it
+    let _job = Job::start();
+    if let Err(e) = handle_browser_action(&bot, &query, db_conn.as_ref()).await {
+        error!("{:?}", e);
+    }
+    Ok(())
+}
+
+async fn handle_browser_action(
+    bot: &Bot,
+    query: &CallbackQuery,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let db_conn = db_conn.ok_or(HandlerError::Config(
+        "DB connection is not available".into(),
+    ))?;
+    let (message, action) = match (
+        &query.message,
+        query.data.as_deref().and_then(BrowserAction::parse),
+    ) {
+        (Some(message), Some(action)) => (message, action),
+        _ => {
+            bot.answer_callback_query(&query.id).await?;
+            return Ok(());
+        }
+    };
+    info!("Bot received a new browser action: {:?}", action);
+    let chat_id = message.chat.id;
+    let thread_id = library_thread(message);
+    let user = &query.from;
+    if get_user_role(bot, &message.chat, user, Some(db_conn)).await < action.role() {
+        bot.answer_callback_query(&query.id)
+            .text(TEXTS.get(
+                "tg_not_permitted",
+                user.language_code.as_deref().unwrap_or("en"),
+            ))
+            .await?;
+        return Ok(());
+    }
+    match action {
+        BrowserAction::Show { kind, page } => {
+            bot.answer_callback_query(&query.id).await?;
+            show_page(bot, message, db_conn, kind, page, user).await?;
+        }
+        BrowserAction::AskDelete { kind, page, id } => {
+            bot.answer_callback_query(&query.id).await?;
+            bot.edit_message_reply_markup(chat_id, message.id)
+                .reply_markup(confirm_keyboard(kind, page, id, user))
+                .await?;
+        }
+        BrowserAction::Delete { kind, page, id } => {
+            if !db_conn.rm_content_by_id(chat_id.0, thread_id, id).await? {
+                return answer_outdated(bot, query).await;
+            }
+            bot.answer_callback_query(&query.id)
+                .text(get_browse_text("deleted", user))
+                .await?;
+            show_page(bot, message, db_conn, kind, page, user).await?;
+        }
+        BrowserAction::Edit { id } => {
+            let item = match db_conn.get_content(chat_id.0, thread_id, id).await? {
+                Some(item) => item,
+                None => return answer_outdated(bot, query).await,
+            };
+            bot.answer_callback_query(&query.id).await?;
+            let prompt = bot
+                .send_message(
+                    chat_id,
+                    format!("{} {}", get_browse_text("edit_prompt", user), item.name),
+                )
+                .reply_markup(ForceReply::new().selective(true))
+                .reply_to_message_id(message.id)
+                .await?;
+            let mut prompts = EDIT_PROMPTS.write().unwrap();
+            if prompts.len() >= EDIT_PROMPTS_SIZE {
+                prompts.clear();
+            }
+            prompts.insert((chat_id, prompt.id), (item.id, user.id));
+        }
+        BrowserAction::Test { id } => {
+            let item = match db_conn.get_content(chat_id.0, thread_id, id).await? {
+                Some(item) => item,
+                None => return answer_outdated(bot, query).await,
+            };
+            bot.answer_callback_query(&query.id)
+                .text(get_browse_text("rendering", user))
+                .await?;
+            let (image, audio) = if item.is_image {
+                (Some(item.data), None)
+            } else {
+                (None, Some(item.data))
+            };
+            let v_data = render_message(
+                &item.words.replace(',', " "),
+                image,
+                audio,
+                None,
+                &Template::default(),
+            )
+            .await?;
+            send_v_data(bot, Some(db_conn), message, message.id, v_data).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn answer_outdated(bot: &Bot, query: &CallbackQuery) -> Result<(), HandlerError> {
+    bot.answer_callback_query(&query.id)
+        .text(get_browse_text("outdated", &query.from))
+        .await?;
+    Ok(())
+}
+
+/// Replace item of browser message with one at page, browser is removed if no items left
+async fn show_page(
+    bot: &Bot,
+    message: &Message,
+    db_conn: &DBConn,
+    kind: ContentKind,
+    page: i64,
+    user: &User,
+) -> Result<(), HandlerError> {
+    let (item, page, count) = match get_page(db_conn, message, kind, page).await? {
+        Some(page) => page,
+        None => {
+            bot.delete_message(message.chat.id, message.id).await?;
+            return Ok(());
+        }
+    };
+    let media = match kind {
+        ContentKind::Image => InputMedia::Photo(
+            InputMediaPhoto::new(media_file(&item)).caption(caption(&item, page, count)),
+        ),
+        ContentKind::Audio => InputMedia::Audio(
+            InputMediaAudio::new(media_file(&item)).caption(caption(&item, page, count)),
+        ),
+    };
+    bot.edit_message_media(message.chat.id, message.id, media)
+        .reply_markup(keyboard(&item, page, count, user))
+        .await?;
+    Ok(())
+}
+
+/// Check that message is a reply to words edit prompt from user who requested it
+pub(super) fn is_edit_reply(message: Message) -> bool {
+    match (message.reply_to_message(), message.from()) {
+        (Some(prompt), Some(user)) => EDIT_PROMPTS
+            .read()
+            .unwrap()
+            .get(&(message.chat.id, prompt.id))
+            .map(|(_, user_id)| *user_id == user.id)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+pub(super) async fn edit_reply_handler(
+    bot: Bot,
+    message: Message,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    if let Err(e) = handle_edit_reply(&bot, &message, db_conn.as_ref()).await {
+        error!("{:?}", e);
+    }
+    Ok(())
+}
+
+/// Change trigger words of content to text of reply to its edit prompt
+async fn handle_edit_reply(
+    bot: &Bot,
+    msg: &Message,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let db_conn = db_conn.ok_or(HandlerError::Config(
+        "DB connection is not available".into(),
+    ))?;
+    let prompt = msg
+        .reply_to_message()
+        .ok_or(HandlerError::InvalidCommand(String::from("Not a reply")))?;
+    let key = (msg.chat.id, prompt.id);
+    let id = match EDIT_PROMPTS.read().unwrap().get(&key) {
+        Some((id, _)) => *id,
+        None => return Ok(()),
+    };
+    // User could be demoted since the prompt was sent
+    if get_role(bot, msg, Some(db_conn)).await < Role::Admin {
+        bot.send_message(msg.chat.id, TEXTS.get_tg("not_permitted", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    let words = msg.text().unwrap_or("").trim();
+    if !WORDS_REGEX.is_match(words) {
+        bot.send_message(msg.chat.id, TEXTS.get_tg("keyword_error", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    EDIT_PROMPTS.write().unwrap().remove(&key);
+    let thread_id = library_thread(msg);
+    let changed = db_conn
+        .change_words_by_id(msg.chat.id.0, thread_id, id, String::from(words))
+        .await?;
+    if !changed {
+        bot.send_message(msg.chat.id, TEXTS.get_tg("browse_outdated", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    bot.send_message(msg.chat.id, TEXTS.get_tg("done_msg", msg))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Bot commands and their menus
+
+use log::info;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope};
+use teloxide::utils::command::{BotCommands, ParseError};
+use teloxide::Bot;
+
+use super::permission::Role;
+use crate::models::error::HandlerError;
+use crate::utils::locale::TEXTS;
+
+const HELP: &str = "help";
+const VERSION: &str = "version";
+const QUOTE: &str = "quote";
+const GEN: &str = "gen";
+#[cfg(feature = "db")]
+const LIST_IMAGE: &str = "listimage";
+#[cfg(feature = "db")]
+const LIST_AUDIO: &str = "listaudio";
+#[cfg(feature = "db")]
+const LIST_WORDS: &str = "listwords";
+#[cfg(feature = "db")]
+const ADD_IMAGE: &str = "addimage";
+#[cfg(feature = "db")]
+const ADD_AUDIO: &str = "addaudio";
+#[cfg(feature = "db")]
+const RM_IMAGE: &str = "rmimage";
+#[cfg(feature = "db")]
+const RM_AUDIO: &str = "rmaudio";
+#[cfg(feature = "db")]
+const EDIT_IMAGE: &str = "editimage";
+#[cfg(feature = "db")]
+const EDIT_AUDIO: &str = "editaudio";
+#[cfg(feature = "db")]
+const EXPORT: &str = "export";
+#[cfg(feature = "db")]
+const IMPORT: &str = "import";
+#[cfg(feature = "db")]
+const ATTRIBUTION: &str = "attribution";
+#[cfg(feature = "db")]
+const ERRORS: &str = "errors";
+#[cfg(feature = "db")]
+const TRIGGERS: &str = "triggers";
+#[cfg(feature = "db")]
+const GRANT: &str = "grant";
+#[cfg(feature = "db")]
+const REVOKE: &str = "revoke";
+#[cfg(feature = "db")]
+const CONTRIBUTORS: &str = "contributors";
+#[cfg(feature = "db")]
+const CANCEL: &str = "cancel";
+
+/// Commands for browsing chat contents
+#[cfg(feature = "db")]
+const LIST_COMMANDS: &[&str] = &[LIST_IMAGE, LIST_AUDIO, LIST_WORDS];
+#[cfg(not(feature = "db"))]
+const LIST_COMMANDS: &[&str] = &[];
+
+/// Commands for managing chat contents and settings
+#[cfg(feature = "db")]
+const CONTENT_COMMANDS: &[&str] = &[
+    ADD_IMAGE,
+    ADD_AUDIO,
+    CANCEL,
+    RM_IMAGE,
+    RM_AUDIO,
+    EDIT_IMAGE,
+    EDIT_AUDIO,
+    EXPORT,
+    IMPORT,
+    ATTRIBUTION,
+    ERRORS,
+    TRIGGERS,
+];
+#[cfg(not(feature = "db"))]
+const CONTENT_COMMANDS: &[&str] = &[];
+
+/// Commands for managing roles of group members
+#[cfg(feature = "db")]
+const ROLE_COMMANDS: &[&str] = &[GRANT, REVOKE, CONTRIBUTORS];
+#[cfg(not(feature = "db"))]
+const ROLE_COMMANDS: &[&str] = &[];
+
+/// All bot commands, arguments are passed as is
+#[derive(BotCommands, Clone, Debug, PartialEq)]
+#[command(rename_rule = "lowercase", parse_with = parse_text)]
+pub enum Command {
+    Help,
+    Start,
+    Version,
+    Quote,
+    Gen(String),
+    ListImage,
+    ListAudio,
+    ListWords,
+    AddImage(String),
+    AddAudio(String),
+    RmImage(String),
+    RmAudio(String),
+    EditImage(String),
+    EditAudio(String),
+    Export,
+    Import(String),
+    Attribution(String),
+    Errors(String),
+    Triggers(String),
+    Grant(String),
+    Revoke(String),
+    Contributors,
+    Cancel,
+}
+
+/// Take whole text after command as its argument
+///
+/// Default parser of `BotCommands` derive maps errors of `String::from_str`, which never fails,
+/// so its error handling is unreachable code.
+fn parse_text(input: String) -> Result<(String,), ParseError> {
+    Ok((input,))
+}
+
+impl Command {
+    /// Minimal role of user allowed to run the command
+    pub fn role(&self) -> Role {
+        match self {
+            Command::Help
+            | Command::Start
+            | Command::Version
+            | Command::Quote
+            | Command::Gen(_)
+            | Command::ListImage
+            | Command::ListAudio
+            | Command::ListWords
+            | Command::Cancel => Role::Member,
+            Command::AddImage(_) | Command::AddAudio(_) => Role::Contributor,
+            Command::RmImage(_)
+            | Command::RmAudio(_)
+            | Command::EditImage(_)
+            | Command::EditAudio(_)
+            | Command::Export
+            | Command::Import(_)
+            | Command::Attribution(_)
+            | Command::Errors(_)
+            | Command::Triggers(_)
+            | Command::Grant(_)
+            | Command::Revoke(_)
+            | Command::Contributors => Role::Admin,
+        }
+    }
+}
+
+/// Names of commands shown in menu of chats of scope
+///
+/// Contributors see the group menu, Telegram has no scope for them.
+fn menu(scope: &BotCommandScope) -> Vec<&'static str> {
+    match scope {
+        BotCommandScope::AllPrivateChats => {
+            [&[HELP, VERSION, GEN], LIST_COMMANDS, CONTENT_COMMANDS].concat()
+        }
+        BotCommandScope::AllChatAdministrators => [
+            &[HELP, VERSION, QUOTE, GEN],
+            LIST_COMMANDS,
+            CONTENT_COMMANDS,
+            ROLE_COMMANDS,
+        ]
+        .concat(),
+        _ => [&[HELP, VERSION, QUOTE, GEN], LIST_COMMANDS].concat(),
+    }
+}
+
+/// Register command menus for private chats, groups and group admins in every language
+///
+/// Descriptions are taken from `tg_cmd_<command>` locale keys, base locale is used
+/// for users whose language has no locale.
+pub async fn register_commands(bot: &Bot) -> Result<(), HandlerError> {
+    let scopes = [
+        BotCommandScope::AllPrivateChats,
+        BotCommandScope::AllGroupChats,
+        BotCommandScope::AllChatAdministrators,
+    ];
+    for scope in scopes {
+        for (ind, lang) in TEXTS.languages().into_iter().enumerate() {
+            let commands = menu(&scope)
+                .into_iter()
+                .map(|name| BotCommand::new(name, TEXTS.get(&format!("tg_cmd_{}", name), lang)));
+            let mut request = bot.set_my_commands(commands).scope(scope.clone());
+            if ind > 0 {
+                request = request.language_code(lang);
+            }
+            request.await?;
+        }
+    }
+    info!("Bot commands are registered.");
+    Ok(())
+}
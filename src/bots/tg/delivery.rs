@@ -0,0 +1,181 @@
+//! Meme delivery and chat lifecycle
+//!
+//! Requests throttled by Telegram flood control are resent after the delay it asks for, up to
+//! `MAX_SEND_RETRIES` times. When a group is upgraded to a supergroup it gets a new id: its
+//! library, settings and contributors are moved there and undelivered meme is resent to the new
+//! chat. Chats which kicked or blocked the bot are marked removed, their data is kept until the
+//! `purge-chats` CLI command drops it, and restored if the bot is added back.
+
+use chrono::Utc;
+use log::{error, info, warn};
+use teloxide::prelude::*;
+use teloxide::requests::{Output, Request};
+use teloxide::types::{InputFile, MessageId};
+use teloxide::{ApiError, Bot, RequestError};
+
+use crate::models::db_conn::DBConn;
+use crate::models::v_data::VData;
+use crate::models::v_data::VData::{Image, Video};
+
+const MAX_SEND_RETRIES: usize = 3;
+const REMOVED_DESCRIPTIONS: [&str; 2] = [
+    "Forbidden: bot was kicked",
+    "Forbidden: bot is not a member",
+];
+
+/// Group upgraded to supergroup, injected by handler tree from migration service messages
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ChatMigration {
+    pub from: ChatId,
+    pub to: ChatId,
+}
+
+impl ChatMigration {
+    /// Get migration from service message sent into the old or the new chat
+    pub(super) fn from_message(message: Message) -> Option<Self> {
+        if let Some(to) = message.migrate_to_chat_id() {
+            return Some(ChatMigration {
+                from: message.chat.id,
+                to,
+            });
+        }
+        message.migrate_from_chat_id().map(|from| ChatMigration {
+            from,
+            to: message.chat.id,
+        })
+    }
+}
+
+/// Send request, waiting out flood control
+///
+/// Parameters:
+///  - request: request to send, it is sent again on every retry
+///
+/// Return: response or the last error
+pub(super) async fn send_retrying<R>(request: &R) -> Result<Output<R>, RequestError>
+where
+    R: Request<Err = RequestError>,
+{
+    let mut retries = 0;
+    loop {
+        match request.send_ref().await {
+            Err(RequestError::RetryAfter(delay)) if retries < MAX_SEND_RETRIES => {
+                retries += 1;
+                warn!("Flood control, retry {} in {:?}.", retries, delay);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Send meme video or image
+///
+/// Parameters:
+///  - bot: bot instance
+///  - chat_id: target chat
+///  - thread_id: forum topic, `None` for general one
+///  - reply_to: message to reply to
+///  - v_data: meme to send
+///  - caption: optional text under meme
+pub(super) async fn send_media(
+    bot: &Bot,
+    chat_id: ChatId,
+    thread_id: Option<i32>,
+    reply_to: Option<MessageId>,
+    v_data: &VData,
+    caption: Option<&str>,
+) -> Result<(), RequestError> {
+    match v_data {
+        Video(video) => {
+            let mut request = bot.send_video(chat_id, InputFile::memory(video.clone()));
+            request.message_thread_id = thread_id;
+            request.reply_to_message_id = reply_to;
+            request.caption = caption.map(String::from);
+            send_retrying(&request).await?;
+        }
+        Image(image) => {
+            let mut request = bot.send_photo(chat_id, InputFile::memory(image.clone()));
+            request.message_thread_id = thread_id;
+            request.reply_to_message_id = reply_to;
+            request.caption = caption.map(String::from);
+            send_retrying(&request).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Move data of upgraded group to its supergroup
+pub(super) async fn migrate_chat(db_conn: Option<&DBConn>, migration: ChatMigration) {
+    let db_conn = match db_conn {
+        Some(db_conn) => db_conn,
+        None => return,
+    };
+    match db_conn.migrate_chat(migration.from.0, migration.to.0).await {
+        Ok(_) => info!("Chat {} is migrated to {}.", migration.from, migration.to),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+/// Migrate data of group on upgrade service message
+pub(super) async fn migration_handler(
+    migration: ChatMigration,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    migrate_chat(db_conn.as_ref(), migration).await;
+    Ok(())
+}
+
+/// Check that bot can't write into chat anymore
+fn is_removed_error(error: &RequestError) -> bool {
+    match error {
+        RequestError::Api(
+            ApiError::BotKicked
+            | ApiError::BotKickedFromSupergroup
+            | ApiError::BotBlocked
+            | ApiError::ChatNotFound
+            | ApiError::UserDeactivated
+            | ApiError::GroupDeactivated,
+        ) => true,
+        // Descriptions of basic groups and channels teloxide doesn't know
+        RequestError::Api(ApiError::Unknown(description)) => REMOVED_DESCRIPTIONS
+            .iter()
+            .any(|prefix| description.starts_with(prefix)),
+        _ => false,
+    }
+}
+
+/// Mark chat removed if request failed because bot was removed from it
+pub(super) async fn handle_send_error(
+    db_conn: Option<&DBConn>,
+    chat_id: ChatId,
+    error: &RequestError,
+) {
+    if is_removed_error(error) {
+        set_chat_present(db_conn, chat_id, false).await;
+    }
+}
+
+/// Mark chat removed or restore it
+///
+/// Parameters:
+///  - db_conn: database connection, nothing is done without it
+///  - chat_id: chat of the bot
+///  - is_present: whether bot is still in chat
+pub(super) async fn set_chat_present(db_conn: Option<&DBConn>, chat_id: ChatId, is_present: bool) {
+    let db_conn = match db_conn {
+        Some(db_conn) => db_conn,
+        None => return,
+    };
+    let result = if is_present {
+        db_conn.restore_chat(chat_id.0).await
+    } else {
+        info!("Bot is removed from chat {}.", chat_id);
+        db_conn
+            .mark_chat_removed(chat_id.0, Utc::now().timestamp())
+            .await
+    };
+    if let Err(e) = result {
+        error!("{:?}", e);
+    }
+}
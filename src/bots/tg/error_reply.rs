@@ -0,0 +1,90 @@
+//! Replies about failed memes
+//!
+//! Error kinds are mapped to `tg_*` locale keys, errors without a key are only logged, e.g.
+//! invalid commands which are answered by their handlers. Chat verbosity set with `/errors`
+//! chooses how much is told: `silent` only logs, `brief` replies with the localized text,
+//! `detailed` adds the reason for admins and notes memes sent as image because video could not
+//! be encoded.
+
+use log::error;
+use teloxide::prelude::*;
+use teloxide::{Bot, RequestError};
+
+use super::permission::{get_role, Role};
+use super::{get_chat_settings, library_thread};
+use crate::models::chat_settings::Verbosity;
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use crate::models::v_data::VData;
+use crate::utils::locale::TEXTS;
+
+/// Locale key of error without `tg_` prefix, `None` if users are not told about it
+fn error_key(err: &HandlerError) -> Option<&'static str> {
+    match err {
+        HandlerError::Render(_) | HandlerError::Encode(_) => Some("render_failed"),
+        HandlerError::Storage(_) | HandlerError::Network(_) => Some("meme_failed"),
+        HandlerError::Telegram(e) => match e.downcast_ref::<RequestError>() {
+            Some(RequestError::RetryAfter(_)) => Some("rate_limited"),
+            _ => None,
+        },
+        HandlerError::InvalidCommand(_) | HandlerError::Config(_) => None,
+    }
+}
+
+/// Reply to message which was not memed because of error
+///
+/// Parameters:
+///  - bot: bot instance
+///  - message: message of the meme request
+///  - db_conn: database connection to get chat verbosity
+///  - err: error of the meme request
+pub(super) async fn report_error(
+    bot: &Bot,
+    message: &Message,
+    db_conn: Option<&DBConn>,
+    err: &HandlerError,
+) {
+    let key = match error_key(err) {
+        Some(key) => key,
+        None => return,
+    };
+    let settings = get_chat_settings(db_conn, message.chat.id.0, library_thread(message)).await;
+    let text = TEXTS.get_tg(key, message);
+    let text = match settings.verbosity {
+        Verbosity::Silent => return,
+        Verbosity::Brief => text,
+        Verbosity::Detailed => {
+            if get_role(bot, message, db_conn).await >= Role::Admin {
+                format!("{}\n{}", text, err)
+            } else {
+                text
+            }
+        }
+    };
+    if let Err(e) = bot
+        .send_message(message.chat.id, text)
+        .reply_to_message_id(message.id)
+        .await
+    {
+        error!("{:?}", e);
+    }
+}
+
+/// Caption of meme sent as image because video could not be encoded, with `detailed` verbosity
+///
+/// Memes are rendered as video, image is only sent when encoding failed.
+pub(super) async fn fallback_caption(
+    message: &Message,
+    db_conn: Option<&DBConn>,
+    v_data: &VData,
+) -> Option<String> {
+    if !matches!(v_data, VData::Image(_)) {
+        return None;
+    }
+    let settings = get_chat_settings(db_conn, message.chat.id.0, library_thread(message)).await;
+    if settings.verbosity == Verbosity::Detailed {
+        Some(TEXTS.get_tg("video_fallback", message))
+    } else {
+        None
+    }
+}
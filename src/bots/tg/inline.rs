@@ -0,0 +1,230 @@
+//! Inline mode
+//!
+//! `@bot <text>` in any chat renders meme-quote with avatar of querying user for every builtin
+//! template. Telegram accepts only file ids or URLs in inline results, so memes are uploaded
+//! into `INLINE_STORAGE_CHAT` first and their file ids are cached. Image results are answered
+//! right away, video is encoded in background and offered on next queries with the same text.
+//!
+//! Telegram sends query on every keystroke, so queries shorter than `INLINE_MIN_LENGTH` are
+//! ignored and query is rendered only if user stops typing for `INLINE_DEBOUNCE_MS`. At most
+//! `INLINE_MAX_ENCODES` videos are encoded at once, memes which didn't get a slot are encoded
+//! on next queries with the same text.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultCachedPhoto, InlineQueryResultCachedVideo, InputFile,
+};
+use teloxide::Bot;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::delivery::send_retrying;
+use super::get_user_avatar;
+use crate::engine::engine::{render_image, render_message};
+use crate::models::error::HandlerError;
+use crate::models::template::Template;
+use crate::models::v_data::VData::{Image, Video};
+use crate::utils::shutdown::Job;
+
+const INLINE_STORAGE_CHAT_KEY: &str = "INLINE_STORAGE_CHAT";
+const INLINE_MIN_LENGTH_KEY: &str = "INLINE_MIN_LENGTH";
+const INLINE_DEBOUNCE_MS_KEY: &str = "INLINE_DEBOUNCE_MS";
+const INLINE_MAX_ENCODES_KEY: &str = "INLINE_MAX_ENCODES";
+const INLINE_CACHE_SIZE: usize = 1000;
+/// Seconds for which Telegram may cache answer, short to pick up encoded videos soon
+const INLINE_CACHE_TIME: u32 = 5;
+
+lazy_static! {
+    static ref INLINE_STORAGE_CHAT: Option<ChatId> = std::env::var(INLINE_STORAGE_CHAT_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .map(ChatId);
+    static ref INLINE_MIN_LENGTH: usize = std::env::var(INLINE_MIN_LENGTH_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(3);
+    static ref INLINE_DEBOUNCE_MS: u64 = std::env::var(INLINE_DEBOUNCE_MS_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(700);
+    static ref INLINE_MAX_ENCODES: usize = std::env::var(INLINE_MAX_ENCODES_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(2);
+    static ref INLINE_CACHE: RwLock<HashMap<InlineKey, InlineMeme>> = RwLock::new(HashMap::new());
+    /// Id of the last query of every user who is typing
+    static ref LAST_QUERIES: RwLock<HashMap<UserId, String>> = RwLock::new(HashMap::new());
+    static ref ENCODE_SLOTS: Arc<Semaphore> = Arc::new(Semaphore::new(*INLINE_MAX_ENCODES));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct InlineKey {
+    user_id: UserId,
+    text: String,
+    template: String,
+}
+
+/// File ids of meme uploaded into storage chat
+///
+/// Video is encoded once per meme, failed encoding is not retried.
+#[derive(Debug, Clone)]
+struct InlineMeme {
+    photo_id: String,
+    video_id: Option<String>,
+    /// Video encoding got a slot
+    video_started: bool,
+}
+
+pub(super) async fn inline_handler(bot: Bot, query: InlineQuery) -> ResponseResult<()> {
+    if let Err(e) = handle_inline_query(&bot, &query).await {
+        error!("{:?}", e);
+    }
+    Ok(())
+}
+
+async fn handle_inline_query(bot: &Bot, query: &InlineQuery) -> Result<(), HandlerError> {
+    let storage = match *INLINE_STORAGE_CHAT {
+        Some(storage) => storage,
+        None => {
+            info!(
+                "Inline query is ignored, {} is not set.",
+                INLINE_STORAGE_CHAT_KEY
+            );
+            return Ok(());
+        }
+    };
+    let text = query.query.trim();
+    if text.chars().count() < *INLINE_MIN_LENGTH || !is_last_query(query).await {
+        return Ok(());
+    }
+    info!("Bot received a new inline query: {}", text);
+
+    let mut avatar: Option<Option<Vec<u8>>> = None;
+    let mut results: Vec<InlineQueryResult> = Vec::new();
+    for name in Template::builtin_names() {
+        let template = Template::builtin(name).ok_or(HandlerError::Config(
+            format!("Template {} not found", name).into(),
+        ))?;
+        let key = InlineKey {
+            user_id: query.from.id,
+            text: String::from(text),
+            template: String::from(name),
+        };
+        let cached = INLINE_CACHE.read().unwrap().get(&key).cloned();
+        if avatar.is_none() && !cached.as_ref().is_some_and(|meme| meme.video_started) {
+            avatar = Some(get_user_avatar(bot, query.from.id).await);
+        }
+        let image = avatar.clone().flatten();
+        let meme = match cached {
+            Some(meme) => meme,
+            None => {
+                let png = render_image(text, image.clone(), None, &template).await?;
+                let meme = InlineMeme {
+                    photo_id: upload_photo(bot, storage, png).await?,
+                    video_id: None,
+                    video_started: false,
+                };
+                cache_meme(key.clone(), meme.clone());
+                meme
+            }
+        };
+        if !meme.video_started {
+            if let Ok(slot) = ENCODE_SLOTS.clone().try_acquire_owned() {
+                if let Some(meme) = INLINE_CACHE.write().unwrap().get_mut(&key) {
+                    meme.video_started = true;
+                }
+                tokio::spawn(upload_video(
+                    bot.clone(),
+                    storage,
+                    key,
+                    image,
+                    template,
+                    slot,
+                ));
+            }
+        }
+        if let Some(video_id) = meme.video_id {
+            results.push(InlineQueryResult::CachedVideo(
+                InlineQueryResultCachedVideo::new(format!("{}_video", name), video_id, name),
+            ));
+        }
+        results.push(InlineQueryResult::CachedPhoto(
+            InlineQueryResultCachedPhoto::new(format!("{}_photo", name), meme.photo_id).title(name),
+        ));
+    }
+    bot.answer_inline_query(&query.id, results)
+        .is_personal(true)
+        .cache_time(INLINE_CACHE_TIME)
+        .await?;
+    Ok(())
+}
+
+/// Wait for user to stop typing
+///
+/// Return: `false` if user sent newer query meanwhile
+async fn is_last_query(query: &InlineQuery) -> bool {
+    let user_id = query.from.id;
+    LAST_QUERIES
+        .write()
+        .unwrap()
+        .insert(user_id, query.id.clone());
+    tokio::time::sleep(Duration::from_millis(*INLINE_DEBOUNCE_MS)).await;
+    let mut last_queries = LAST_QUERIES.write().unwrap();
+    if last_queries.get(&user_id) != Some(&query.id) {
+        return false;
+    }
+    last_queries.remove(&user_id);
+    true
+}
+
+fn cache_meme(key: InlineKey, meme: InlineMeme) {
+    let mut cache = INLINE_CACHE.write().unwrap();
+    if cache.len() >= INLINE_CACHE_SIZE && !cache.contains_key(&key) {
+        cache.clear();
+    }
+    cache.insert(key, meme);
+}
+
+async fn upload_photo(bot: &Bot, storage: ChatId, png: Vec<u8>) -> Result<String, HandlerError> {
+    let message = send_retrying(&bot.send_photo(storage, InputFile::memory(png))).await?;
+    message
+        .photo()
+        .and_then(|sizes| sizes.last())
+        .map(|size| size.file.id.clone())
+        .ok_or(HandlerError::Telegram("Uploaded photo has no file".into()))
+}
+
+/// Encode meme video and remember its file id for next queries, encoding slot is freed when done
+async fn upload_video(
+    bot: Bot,
+    storage: ChatId,
+    key: InlineKey,
+    image: Option<Vec<u8>>,
+    template: Template,
+    _slot: OwnedSemaphorePermit,
+) {
+    let _job = Job::start();
+    let video = match render_message(&key.text, image, None, None, &template).await {
+        Ok(Video(video)) => video,
+        Ok(Image(_)) => return,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    let video_id = match send_retrying(&bot.send_video(storage, InputFile::memory(video))).await {
+        Ok(message) => message.video().map(|video| video.file.id.clone()),
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    if let Some(meme) = INLINE_CACHE.write().unwrap().get_mut(&key) {
+        meme.video_id = video_id;
+    }
+}
@@ -0,0 +1,262 @@
+//! Content files of messages
+//!
+//! Content can be attached to command message itself or to the message it replies to,
+//! e.g. `/addimage words` as a reply to a photo or a sticker. Forwarded messages carry
+//! media the same way as own ones.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+
+use super::download_file;
+use crate::models::chat_stats::ChatStats;
+use crate::models::content_limits::{ContentLimits, LimitError};
+use crate::models::content_model::{ContentKind, ContentModel};
+use crate::models::db_conn::{DBConn, StoreError};
+use crate::models::error::HandlerError;
+use crate::utils::image_utils::normalize_image;
+use crate::utils::locale::TEXTS;
+
+const MB: i64 = 1024 * 1024;
+
+/// Content file found in message, downloaded only when it is stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaFile {
+    pub file_id: String,
+    /// Content name, images are named as JPEG they are converted to
+    pub file_name: String,
+    /// Message with the file, command message or replied one
+    pub message_id: MessageId,
+    /// File size in bytes
+    #[serde(default)]
+    pub size: u32,
+    /// Audio duration in seconds
+    #[serde(default)]
+    pub duration: Option<u32>,
+}
+
+/// Reason content file can not be stored
+#[derive(Debug)]
+pub(super) enum MediaError {
+    Limit(LimitError),
+    Download,
+    /// Image can not be decoded
+    Format(HandlerError),
+    Db(HandlerError),
+}
+
+impl From<MediaError> for HandlerError {
+    fn from(err: MediaError) -> Self {
+        match err {
+            MediaError::Limit(err) => {
+                HandlerError::InvalidCommand(format!("Limit exceeded: {:?}", err))
+            }
+            MediaError::Download => HandlerError::Telegram("Invalid file load".into()),
+            MediaError::Format(err) | MediaError::Db(err) => err,
+        }
+    }
+}
+
+impl From<StoreError> for MediaError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::Limit(err) => MediaError::Limit(err),
+            StoreError::Db(err) => MediaError::Db(err),
+        }
+    }
+}
+
+impl From<LimitError> for MediaError {
+    fn from(err: LimitError) -> Self {
+        MediaError::Limit(err)
+    }
+}
+
+/// Find content file of kind in message or in replied message
+///
+/// Images are photos (largest size), image documents and static stickers, audio are audio
+/// files and audio documents.
+pub(super) fn find_media(msg: &Message, kind: ContentKind) -> Option<MediaFile> {
+    get_media(msg, kind).or_else(|| {
+        msg.reply_to_message()
+            .and_then(|reply| get_media(reply, kind))
+    })
+}
+
+fn get_media(msg: &Message, kind: ContentKind) -> Option<MediaFile> {
+    let mut duration = None;
+    let (file, file_name) = match kind {
+        ContentKind::Image => {
+            if let Some(size) = msg.photo().and_then(|sizes| sizes.last()) {
+                (&size.file, None)
+            } else if let Some(sticker) = msg.sticker().filter(|sticker| sticker.is_raster()) {
+                (&sticker.file, None)
+            } else {
+                let doc = msg.document().filter(|doc| {
+                    doc.mime_type
+                        .as_ref()
+                        .map(|mime| mime.type_() == mime::IMAGE)
+                        .unwrap_or(false)
+                })?;
+                (&doc.file, doc.file_name.as_deref())
+            }
+        }
+        ContentKind::Audio => {
+            if let Some(audio) = msg.audio() {
+                duration = Some(audio.duration);
+                (&audio.file, audio.file_name.as_deref())
+            } else {
+                let doc = msg.document().filter(|doc| {
+                    doc.mime_type
+                        .as_ref()
+                        .map(|mime| mime.type_() == mime::AUDIO)
+                        .unwrap_or(false)
+                })?;
+                (&doc.file, doc.file_name.as_deref())
+            }
+        }
+    };
+    let file_name = match (kind, file_name) {
+        (ContentKind::Image, Some(name)) => Path::new(name)
+            .with_extension("jpg")
+            .to_string_lossy()
+            .to_string(),
+        (ContentKind::Image, None) => format!("{}.jpg", file.unique_id),
+        (ContentKind::Audio, Some(name)) => String::from(name),
+        (ContentKind::Audio, None) => format!("{}.mp3", file.unique_id),
+    };
+    Some(MediaFile {
+        file_id: file.id.clone(),
+        file_name,
+        message_id: msg.id,
+        size: file.size,
+        duration,
+    })
+}
+
+/// Download content file and add it to chat library, images are normalized for storing
+///
+/// File is checked against limits with its size from message before download, quota is checked
+/// again with stored size when it is inserted.
+///
+/// Parameters:
+///  - bot: bot instance
+///  - db_conn: database connection
+///  - chat_id: chat to store content in
+///  - thread_id: forum topic to store content in, 0 for whole chat
+///  - words: trigger words of content
+///  - media: file to download
+///  - kind: kind of content
+///
+/// Return: reason why content can not be stored
+pub(super) async fn store_media(
+    bot: &Bot,
+    db_conn: &DBConn,
+    chat_id: ChatId,
+    thread_id: i64,
+    words: String,
+    media: &MediaFile,
+    kind: ContentKind,
+) -> Result<(), MediaError> {
+    let limits = ContentLimits::new();
+    limits.check_file(kind, media.size as i64, media.duration)?;
+    let stats = db_conn
+        .get_chat_stats(chat_id.0)
+        .await
+        .map_err(MediaError::Db)?;
+    limits.check_quota(&stats, 1, media.size as i64)?;
+    let data = download_file(bot, media.file_id.clone())
+        .await
+        .ok_or(MediaError::Download)?;
+    let data = match kind {
+        ContentKind::Image => {
+            normalize_image(data, limits.image_max_side).map_err(MediaError::Format)?
+        }
+        ContentKind::Audio => data,
+    };
+    let item = ContentModel {
+        thread_id,
+        ..ContentModel::from(
+            chat_id.0,
+            kind.is_image(),
+            words,
+            media.file_name.clone(),
+            data,
+        )
+    };
+    db_conn.add_content(item, &limits).await?;
+    Ok(())
+}
+
+/// Get content statistics imported contents are counted against
+///
+/// Parameters:
+///  - db_conn: database connection
+///  - chat_id: chat to import contents into
+///  - thread_id: forum topic to import contents into, 0 for whole chat
+///  - replace: current contents of the topic are removed on import
+pub(super) async fn import_stats(
+    db_conn: &DBConn,
+    chat_id: ChatId,
+    thread_id: i64,
+    replace: bool,
+) -> Result<ChatStats, HandlerError> {
+    // Quota is shared by all topics of chat
+    match replace {
+        true => db_conn.get_chat_stats_without(chat_id.0, thread_id).await,
+        false => db_conn.get_chat_stats(chat_id.0).await,
+    }
+}
+
+/// Check imported contents against limits, images are normalized for storing
+///
+/// Parameters:
+///  - limits: content limits of chat
+///  - stats: contents imported ones are counted with, see `import_stats`
+///  - items: unpacked contents
+///
+/// Return: contents to store or reason why they can not be stored
+pub(super) fn prepare_import(
+    limits: &ContentLimits,
+    stats: &ChatStats,
+    items: Vec<ContentModel>,
+) -> Result<Vec<ContentModel>, MediaError> {
+    let mut result = Vec::with_capacity(items.len());
+    for mut item in items {
+        let kind = ContentKind::from_is_image(item.is_image);
+        limits.check_file(kind, item.data.len() as i64, None)?;
+        if item.is_image {
+            item.data =
+                normalize_image(item.data, limits.image_max_side).map_err(MediaError::Format)?;
+        }
+        result.push(item);
+    }
+    let size = result.iter().map(|item| item.data.len() as i64).sum();
+    limits.check_quota(stats, result.len() as i64, size)?;
+    Ok(result)
+}
+
+/// Localized description of limit error
+pub(super) fn limit_text(err: &LimitError, lang: &str) -> String {
+    let (key, value) = match err {
+        LimitError::TooManyItems(max) => ("tg_limit_items", *max),
+        LimitError::ChatTooLarge(max) => ("tg_limit_chat_size", max / MB),
+        LimitError::FileTooLarge(max) => ("tg_limit_file_size", max / MB),
+        LimitError::AudioTooLong(max) => ("tg_limit_audio_duration", *max as i64),
+    };
+    format!("{} {}", TEXTS.get(key, lang), value)
+}
+
+/// Localized description of media error
+pub(super) fn media_error_text(err: &MediaError, kind: ContentKind, lang: &str) -> String {
+    let key = match (err, kind) {
+        (MediaError::Limit(err), _) => return limit_text(err, lang),
+        (MediaError::Download, ContentKind::Image) => "tg_image_add_dw_error",
+        (MediaError::Download, ContentKind::Audio) => "tg_audio_add_dw_error",
+        (MediaError::Format(_), _) => "tg_image_add_format_invalid",
+        (MediaError::Db(_), _) => "tg_error_msg",
+    };
+    TEXTS.get(key, lang)
+}
@@ -0,0 +1,1356 @@
+//! Telegram bot implementation
+
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use regex::Regex;
+use teloxide::dispatching::{ShutdownToken, UpdateHandler};
+use teloxide::net::Download;
+use teloxide::prelude::*;
+use teloxide::requests::Requester;
+use teloxide::types::{
+    ChatMemberUpdated, InputFile, Me, MediaAudio, MediaDocument, MediaKind, MediaPhoto, MediaText,
+    MessageCommon, MessageId, MessageKind, User,
+};
+use teloxide::utils::command::{BotCommands, ParseError};
+use teloxide::{Bot, RequestError};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+#[cfg(feature = "db")]
+use self::add_flow::{cancel_flow, start_flow};
+use self::api::{create_bot, read_local_file};
+#[cfg(feature = "db")]
+use self::browser::{browser_handler, edit_reply_handler, is_edit_reply, send_browser};
+use self::command::{register_commands, Command};
+use self::delivery::{
+    handle_send_error, migrate_chat, migration_handler, send_media, set_chat_present, ChatMigration,
+};
+use self::error_reply::{fallback_caption, report_error};
+use self::inline::inline_handler;
+#[cfg(feature = "db")]
+use self::media::{
+    find_media, import_stats, limit_text, media_error_text, prepare_import, store_media, MediaError,
+};
+use self::permission::{chat_member_handler, forget_admins, get_role, Role};
+use self::webhook::webhook_options;
+use crate::engine::engine::{build_message, render_message};
+use crate::models::attribution::Attribution;
+use crate::models::chat_settings::ChatSettings;
+#[cfg(feature = "db")]
+use crate::models::content_archive::{pack_contents, unpack_contents, UnpackError};
+#[cfg(feature = "db")]
+use crate::models::content_limits::ContentLimits;
+use crate::models::content_model::ContentKind;
+#[cfg(feature = "db")]
+use crate::models::contributor::Contributor;
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use crate::models::image_source::ImageSource;
+use crate::models::template::Template;
+use crate::models::v_data::VData;
+use crate::utils::locale::{Locale, TEXTS};
+use crate::utils::shutdown::{active_jobs, cleanup, wait_jobs, Job, SHUTDOWN_TIMEOUT};
+use crate::utils::string_utils::contains_in;
+use crate::utils::version::VERSION_STRING;
+use teloxide::types::MessageKind::Common;
+
+#[cfg(feature = "db")]
+mod add_flow;
+mod api;
+#[cfg(feature = "db")]
+mod browser;
+mod command;
+mod delivery;
+mod error_reply;
+mod inline;
+#[cfg(feature = "db")]
+mod media;
+mod permission;
+mod webhook;
+
+pub use self::webhook::webhook_listener;
+
+const UNKNOWN_USER: &str = "unknown";
+
+const QUOTE_TRIGGERS_KEY: &str = "QUOTE_TRIGGERS";
+const IMAGE_SOURCES_KEY: &str = "IMAGE_SOURCES";
+const EDITED_MESSAGES_KEY: &str = "EDITED_MESSAGES";
+const CHANNEL_POSTS_KEY: &str = "CHANNEL_POSTS";
+const TOPIC_LIBRARIES_KEY: &str = "TOPIC_LIBRARIES";
+const MEMED_MESSAGES_SIZE: usize = 1000;
+
+#[cfg(feature = "db")]
+const IMPORT_REPLACE_ARG: &str = "replace";
+#[cfg(feature = "db")]
+const SETTING_ON_ARG: &str = "on";
+#[cfg(feature = "db")]
+const SETTING_OFF_ARG: &str = "off";
+#[cfg(feature = "db")]
+const EXPORT_FILE_NAME: &str = "contents.zip";
+
+#[cfg(feature = "db")]
+const PRIVATE_HELP_KEY: &str = "private_help_with_db";
+#[cfg(not(feature = "db"))]
+const PRIVATE_HELP_KEY: &str = "private_help_text";
+#[cfg(feature = "db")]
+const GROUP_HELP_KEY: &str = "group_help_with_db";
+#[cfg(not(feature = "db"))]
+const GROUP_HELP_KEY: &str = "group_help_without_db";
+
+lazy_static! {
+    static ref WORDS_REGEX: Regex = regex::Regex::new("[a-zA-Z0-9а-яА-Я,]+").unwrap();
+    static ref CHANGE_WORDS_REGEX: Regex = regex::Regex::new("(.+) ([a-zA-Z0-9а-яА-Я,]+)").unwrap();
+    static ref QUOTE_TRIGGERS: Vec<String> = std::env::var(QUOTE_TRIGGERS_KEY)
+        .unwrap_or_default()
+        .to_lowercase()
+        .split(',')
+        .filter(|i| !i.is_empty())
+        .map(|i| i.trim().to_string())
+        .collect();
+    static ref IMAGE_SOURCES: Vec<ImageSource> = ImageSource::parse_chain(
+        &std::env::var(IMAGE_SOURCES_KEY).unwrap_or(String::from(ImageSource::DEFAULT_CHAIN))
+    );
+    static ref EDITED_MESSAGES: bool = std::env::var(EDITED_MESSAGES_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(false);
+    static ref CHANNEL_POSTS: bool = std::env::var(CHANNEL_POSTS_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(false);
+    static ref TOPIC_LIBRARIES: bool = std::env::var(TOPIC_LIBRARIES_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(false);
+    /// Recently memed messages, edits of them are not memed again
+    static ref MEMED_MESSAGES: RwLock<HashSet<(ChatId, MessageId)>> = RwLock::new(HashSet::new());
+}
+
+/// Run TG bot and await
+///
+/// Parameters:
+///  - db_conn: shared database connection, injected into every handler
+pub async fn run_tg_bot(db_conn: Option<DBConn>) {
+    let bot = create_bot();
+    if let Err(e) = register_commands(&bot).await {
+        error!("{:?}", e);
+    }
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler_tree())
+        .dependencies(handler_deps(db_conn))
+        .build();
+    let deadline = Arc::new(Notify::new());
+    tokio::spawn(shutdown_on_signal(
+        dispatcher.shutdown_token(),
+        deadline.clone(),
+    ));
+    let dispatching = async {
+        match webhook_options() {
+            Some(options) => {
+                let listener = webhook_listener(bot, options).await;
+                dispatcher
+                    .dispatch_with_listener(
+                        listener,
+                        LoggingErrorHandler::with_custom_text("Webhook listener error"),
+                    )
+                    .await
+            }
+            None => dispatcher.dispatch().await,
+        }
+        // Spawned renders are not awaited by dispatcher
+        wait_jobs().await;
+    };
+    tokio::select! {
+        _ = dispatching => info!("All jobs are finished."),
+        _ = deadline.notified() => warn!("Shutdown timeout is exceeded."),
+    }
+    cleanup();
+}
+
+/// Wait for Ctrl-C or SIGTERM sent by service managers and containers
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+/// Wait for Ctrl-C
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+/// Stop taking updates on Ctrl-C or SIGTERM and notify `deadline` when in-flight jobs are out
+/// of time
+async fn shutdown_on_signal(token: ShutdownToken, deadline: Arc<Notify>) {
+    loop {
+        if let Err(e) = shutdown_signal().await {
+            error!("Failed to listen for shutdown signal: {:?}", e);
+            return;
+        }
+        if token.shutdown().is_ok() {
+            info!("Shutting down, waiting for {} jobs...", active_jobs());
+            tokio::time::sleep(*SHUTDOWN_TIMEOUT).await;
+            deadline.notify_one();
+            return;
+        }
+        info!("Dispatcher isn't running, ignoring shutdown signal.");
+    }
+}
+
+/// Handler tree of all bot updates
+///
+/// Edited messages with `EDITED_MESSAGES` and channel posts with `CHANNEL_POSTS` are memed
+/// like new messages, edited commands are ignored.
+///
+/// Besides `handler_deps` handlers need `Bot`, `Me` and `Update` dependencies,
+/// dispatcher provides them.
+pub fn handler_tree() -> UpdateHandler<RequestError> {
+    let commands = dptree::filter_map(parse_command)
+        .branch(dptree::filter_async(is_permitted).endpoint(command_handler))
+        .endpoint(not_permitted_handler);
+    let unknown_commands = dptree::filter(is_unknown_command).endpoint(unknown_command_handler);
+    let channel_posts = Update::filter_channel_post()
+        .filter(|| *CHANNEL_POSTS)
+        .branch(commands.clone())
+        .branch(unknown_commands.clone())
+        .endpoint(handler);
+    let edited_messages = dptree::filter(|| *EDITED_MESSAGES)
+        .branch(Update::filter_edited_message().endpoint(edited_handler))
+        .branch(
+            Update::filter_edited_channel_post()
+                .filter(|| *CHANNEL_POSTS)
+                .endpoint(edited_handler),
+        );
+    let messages = Update::filter_message()
+        .branch(dptree::filter_map(ChatMigration::from_message).endpoint(migration_handler))
+        .branch(commands)
+        .branch(unknown_commands);
+    #[cfg(feature = "db")]
+    let messages = messages
+        .branch(dptree::filter(is_edit_reply).endpoint(edit_reply_handler))
+        .branch(add_flow::message_handler());
+    let tree = dptree::entry()
+        .branch(messages.endpoint(handler))
+        .branch(channel_posts)
+        .branch(edited_messages)
+        .branch(Update::filter_inline_query().endpoint(inline_handler))
+        .branch(Update::filter_chat_member().endpoint(chat_member_handler))
+        .branch(Update::filter_my_chat_member().endpoint(my_chat_member_handler));
+    #[cfg(feature = "db")]
+    let tree = tree.branch(
+        Update::filter_callback_query()
+            .branch(add_flow::callback_handler())
+            .endpoint(browser_handler),
+    );
+    tree
+}
+
+/// Shared dependencies of handler tree
+///
+/// Parameters:
+///  - db_conn: shared database connection
+pub fn handler_deps(db_conn: Option<DBConn>) -> DependencyMap {
+    #[allow(unused_mut)]
+    let mut deps = dptree::deps![db_conn.clone()];
+    // Dialogue storage, the connection is always set up with `db` feature
+    #[cfg(feature = "db")]
+    if let Some(db_conn) = db_conn {
+        deps.insert(Arc::new(db_conn));
+    }
+    deps
+}
+
+async fn handler(bot: Bot, message: Message, db_conn: Option<DBConn>) -> ResponseResult<()> {
+    let _job = Job::start();
+    return match handle_message(&bot, &message, db_conn.as_ref()).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("{:?}", e);
+            report_error(&bot, &message, db_conn.as_ref(), &e).await;
+            Ok(())
+        }
+    };
+}
+
+/// Track status of bot itself in chat
+///
+/// Cached admins are forgotten, chat is marked removed when bot leaves it and restored when
+/// bot is back.
+async fn my_chat_member_handler(
+    update: ChatMemberUpdated,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    forget_admins(update.chat.id);
+    let is_present = update.new_chat_member.kind.is_present();
+    set_chat_present(db_conn.as_ref(), update.chat.id, is_present).await;
+    Ok(())
+}
+
+/// Meme edited message like a new one unless it is already memed
+async fn edited_handler(bot: Bot, message: Message, db_conn: Option<DBConn>) -> ResponseResult<()> {
+    if is_memed(message.chat.id, message.id) {
+        return Ok(());
+    }
+    handler(bot, message, db_conn).await
+}
+
+async fn command_handler(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    db_conn: Option<DBConn>,
+) -> ResponseResult<()> {
+    info!("Bot received a new command: {:?}", command);
+    let _job = Job::start();
+    // Other commands reply about their errors themselves
+    let is_meme = matches!(command, Command::Quote | Command::Gen(_));
+    if let Err(e) = exec_command(&bot, &message, command, db_conn.as_ref()).await {
+        error!("{:?}", e);
+        if is_meme {
+            report_error(&bot, &message, db_conn.as_ref(), &e).await;
+        }
+    }
+    Ok(())
+}
+
+async fn not_permitted_handler(bot: Bot, message: Message, command: Command) -> ResponseResult<()> {
+    info!(
+        "@{} is not permitted to run {:?}.",
+        get_username(&message),
+        command
+    );
+    bot.send_message(message.chat.id, TEXTS.get_tg("not_permitted", &message))
+        .reply_to_message_id(message.id)
+        .await?;
+    Ok(())
+}
+
+async fn unknown_command_handler(bot: Bot, message: Message) -> ResponseResult<()> {
+    bot.send_message(message.chat.id, TEXTS.get_tg("unknown_command", &message))
+        .reply_to_message_id(message.id)
+        .await?;
+    Ok(())
+}
+
+/// Parse command of this bot from message text or caption
+fn parse_command(message: Message, me: Me) -> Option<Command> {
+    Command::parse(get_command_text(&message)?, me.username()).ok()
+}
+
+/// Check for unknown command addressed to this bot: any in private chat or `/cmd@bot` in group
+fn is_unknown_command(message: Message, me: Me) -> bool {
+    let text = match get_command_text(&message) {
+        Some(text) if text.starts_with('/') => text,
+        _ => return false,
+    };
+    if !matches!(
+        Command::parse(text, me.username()),
+        Err(ParseError::UnknownCommand(_))
+    ) {
+        return false;
+    }
+    let mention = format!("@{}", me.username()).to_lowercase();
+    message.chat.is_private()
+        || text
+            .split_whitespace()
+            .next()
+            .map(|cmd| cmd.to_lowercase().ends_with(&mention))
+            .unwrap_or(false)
+}
+
+async fn is_permitted(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    db_conn: Option<DBConn>,
+) -> bool {
+    let required = command.role();
+    required == Role::Member || get_role(&bot, &message, db_conn.as_ref()).await >= required
+}
+
+fn get_username(message: &Message) -> String {
+    message
+        .from()
+        .and_then(|user| user.username.clone())
+        .unwrap_or(String::from(UNKNOWN_USER))
+}
+
+/// Forum topic of message, `None` for messages outside of topics and in General topic
+fn topic_id(message: &Message) -> Option<i32> {
+    match &message.kind {
+        Common(MessageCommon {
+            is_topic_message: true,
+            ..
+        }) => message.thread_id,
+        _ => None,
+    }
+}
+
+/// Thread of content library and settings for message
+///
+/// With `TOPIC_LIBRARIES` every forum topic has its own ones, otherwise the whole chat shares
+/// library and settings of thread 0.
+fn library_thread(message: &Message) -> i64 {
+    match topic_id(message) {
+        Some(thread_id) if *TOPIC_LIBRARIES => thread_id as i64,
+        _ => 0,
+    }
+}
+
+async fn download_file(bot: &Bot, file_id: String) -> Option<Vec<u8>> {
+    if let Ok(file) = bot.get_file(file_id).await {
+        if let Some(data) = read_local_file(&file.path) {
+            return Some(data);
+        }
+        let mut out: Vec<u8> = Vec::new();
+        let mut cursor = Cursor::new(&mut out);
+        if let Ok(_) = bot.download_file(&file.path, &mut cursor).await {
+            if !out.is_empty() {
+                return Some(out);
+            }
+        }
+    }
+    None
+}
+
+/// Text of message which may be a command, photo captions are `/gen` with the photo
+fn get_command_text(message: &Message) -> Option<&str> {
+    get_text(message).or_else(|| message.photo().and(message.caption()))
+}
+
+#[cfg(not(feature = "db"))]
+fn get_text(message: &Message) -> Option<&str> {
+    message.text()
+}
+
+#[cfg(feature = "db")]
+fn get_text(message: &Message) -> Option<&str> {
+    match &message.kind {
+        Common(MessageCommon {
+            media_kind: MediaKind::Text(MediaText { text, .. }),
+            ..
+        }) => Some(text),
+        Common(MessageCommon {
+            media_kind: MediaKind::Document(MediaDocument { caption, .. }),
+            ..
+        }) => caption.as_deref(),
+        Common(MessageCommon {
+            media_kind: MediaKind::Audio(MediaAudio { caption, .. }),
+            ..
+        }) => caption.as_deref(),
+        Common(MessageCommon {
+            media_kind: MediaKind::Photo(MediaPhoto { caption, .. }),
+            ..
+        }) => caption.as_deref(),
+        _ => None,
+    }
+}
+
+async fn get_custom_words_from_db(
+    db_conn: Option<&DBConn>,
+    chat_id: i64,
+    thread_id: i64,
+) -> Option<String> {
+    if let Some(db_conn) = db_conn {
+        if let Ok(words) = db_conn.get_words(chat_id, thread_id).await {
+            if words.is_empty() {
+                None
+            } else {
+                Some(words)
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+async fn get_chat_settings(db_conn: Option<&DBConn>, chat_id: i64, thread_id: i64) -> ChatSettings {
+    match db_conn {
+        Some(db_conn) => db_conn
+            .get_settings(chat_id, thread_id)
+            .await
+            .unwrap_or_else(|_| ChatSettings::new(chat_id, thread_id)),
+        None => ChatSettings::new(chat_id, thread_id),
+    }
+}
+
+/// Attribution with `@username` of user or display name if user has no username
+fn user_attribution(user: &User, date: DateTime<Utc>) -> Attribution {
+    Attribution::new(
+        user.mention().unwrap_or_else(|| user.full_name()),
+        Some(date),
+    )
+}
+
+/// Attribution of message author, title of chat for messages sent on behalf of it
+fn message_attribution(message: &Message) -> Option<Attribution> {
+    match (message.from(), message.sender_chat()) {
+        (Some(user), _) => Some(user_attribution(user, message.date)),
+        (None, Some(chat)) => Some(Attribution::new(
+            chat.title().unwrap_or(UNKNOWN_USER).to_string(),
+            Some(message.date),
+        )),
+        (None, None) => None,
+    }
+}
+
+async fn get_custom_content(
+    db_conn: Option<&DBConn>,
+    chat_id: i64,
+    thread_id: i64,
+    custom_words: Option<String>,
+    data: &str,
+    is_image: bool,
+) -> Option<Vec<u8>> {
+    let words = contains_in(custom_words?, String::from(data));
+    if words.is_empty() {
+        return None;
+    }
+    db_conn?
+        .get_random_content(chat_id, thread_id, is_image, words)
+        .await
+        .ok()
+        .map(|content| content.data)
+}
+
+async fn get_user_avatar(bot: &Bot, user_id: UserId) -> Option<Vec<u8>> {
+    let photos = bot.get_user_profile_photos(user_id).limit(1).await.ok()?;
+    let photo_id = photos
+        .photos
+        .first()
+        .and_then(|s| s.last())
+        .map(|i| i.file.id.clone())?;
+    download_file(bot, photo_id).await
+}
+
+async fn get_chat_photo(bot: &Bot, chat_id: ChatId) -> Option<Vec<u8>> {
+    let photo = bot.get_chat(chat_id).await.ok()?.photo?;
+    download_file(bot, photo.big_file_id).await
+}
+
+/// Get meme image from first source of `IMAGE_SOURCES` which has one
+///
+/// Sources are requested only when previous ones have nothing, so Telegram
+/// is not asked for avatar or chat photo if custom content is found.
+async fn get_image(
+    bot: &Bot,
+    message: &Message,
+    user: Option<&User>,
+    db_conn: Option<&DBConn>,
+    custom_words: Option<String>,
+    data: &str,
+) -> Option<Vec<u8>> {
+    for source in IMAGE_SOURCES.iter() {
+        let image = match source {
+            ImageSource::Content => {
+                get_custom_content(
+                    db_conn,
+                    message.chat.id.0,
+                    library_thread(message),
+                    custom_words.clone(),
+                    data,
+                    true,
+                )
+                .await
+            }
+            ImageSource::Avatar => match user {
+                Some(user) => get_user_avatar(bot, user.id).await,
+                None => None,
+            },
+            ImageSource::ChatPhoto => get_chat_photo(bot, message.chat.id).await,
+            ImageSource::Stock => break,
+        };
+        if image.is_some() {
+            info!("Use {:?} image.", source);
+            return image;
+        }
+    }
+    info!("Use default image.");
+    None
+}
+
+/// Send meme into chat and forum topic of message as reply to memed message
+///
+/// Meme for group upgraded to supergroup is resent to the new chat after its data is migrated,
+/// chat is marked removed if bot was kicked or blocked.
+///
+/// Parameters:
+///  - bot: bot instance
+///  - db_conn: database connection
+///  - message: message of the meme request
+///  - reply_to: memed message, the request itself or message replied by it
+///  - v_data: meme to send
+async fn send_v_data(
+    bot: &Bot,
+    db_conn: Option<&DBConn>,
+    message: &Message,
+    reply_to: MessageId,
+    v_data: VData,
+) -> Result<(), HandlerError> {
+    let chat_id = message.chat.id;
+    let caption = fallback_caption(message, db_conn, &v_data).await;
+    let caption = caption.as_deref();
+    match send_media(
+        bot,
+        chat_id,
+        topic_id(message),
+        Some(reply_to),
+        &v_data,
+        caption,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(RequestError::MigrateToChatId(new_chat_id)) => {
+            let migration = ChatMigration {
+                from: chat_id,
+                to: ChatId(new_chat_id),
+            };
+            migrate_chat(db_conn, migration).await;
+            send_media(bot, migration.to, None, None, &v_data, caption).await?;
+        }
+        Err(e) => {
+            handle_send_error(db_conn, chat_id, &e).await;
+            return Err(e.into());
+        }
+    }
+    remember_memed(chat_id, reply_to);
+    Ok(())
+}
+
+/// Remember memed message so its edits are not memed again
+fn remember_memed(chat_id: ChatId, message_id: MessageId) {
+    let mut memed = MEMED_MESSAGES.write().unwrap();
+    if memed.len() >= MEMED_MESSAGES_SIZE {
+        memed.clear();
+    }
+    memed.insert((chat_id, message_id));
+}
+
+fn is_memed(chat_id: ChatId, message_id: MessageId) -> bool {
+    MEMED_MESSAGES
+        .read()
+        .unwrap()
+        .contains(&(chat_id, message_id))
+}
+
+/// Check that message is one of quote triggers, `/quote` command is handled by dispatcher
+fn is_quote_request(data: &str) -> bool {
+    QUOTE_TRIGGERS.contains(&data.trim().to_lowercase())
+}
+
+/// Meme replied message with avatar and name of its author
+async fn quote_message(
+    bot: &Bot,
+    message: &Message,
+    reply: &Message,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let text = match get_text(reply) {
+        Some(text) if !text.is_empty() => text,
+        _ => {
+            bot.send_message(message.chat.id, TEXTS.get_tg("quote_no_text", message))
+                .reply_to_message_id(message.id)
+                .await?;
+            return Ok(());
+        }
+    };
+    let author = reply.from();
+    let image = match author {
+        Some(author) => get_user_avatar(bot, author.id).await,
+        None => None,
+    };
+    let attribution = author.map(|author| user_attribution(author, reply.date));
+    let (chat_id, thread_id) = (message.chat.id.0, library_thread(message));
+    let custom_words = get_custom_words_from_db(db_conn, chat_id, thread_id).await;
+    let audio = get_custom_content(db_conn, chat_id, thread_id, custom_words, text, false).await;
+    info!("Quote message: {}", text);
+    let v_data = render_message(
+        text,
+        image,
+        audio,
+        attribution.as_ref(),
+        &Template::default(),
+    )
+    .await?;
+    send_v_data(bot, db_conn, message, reply.id, v_data).await
+}
+
+/// Meme text of `/gen`, contents of private chat are personal library of user
+async fn gen_message(
+    bot: &Bot,
+    message: &Message,
+    user: Option<&User>,
+    db_conn: Option<&DBConn>,
+    text: &str,
+    image: Option<Vec<u8>>,
+) -> Result<(), HandlerError> {
+    if text.is_empty() {
+        bot.send_message(message.chat.id, TEXTS.get_tg("invalid_arguments", message))
+            .reply_to_message_id(message.id)
+            .await?;
+        return Ok(());
+    }
+    info!("Generate meme: {}", text);
+    let (chat_id, thread_id) = (message.chat.id.0, library_thread(message));
+    let custom_words = get_custom_words_from_db(db_conn, chat_id, thread_id).await;
+    let image = match image {
+        Some(image) => Some(image),
+        None => get_image(bot, message, user, db_conn, custom_words.clone(), text).await,
+    };
+    let audio = get_custom_content(db_conn, chat_id, thread_id, custom_words, text, false).await;
+    let attribution = if get_chat_settings(db_conn, chat_id, thread_id)
+        .await
+        .attribution
+    {
+        message_attribution(message)
+    } else {
+        None
+    };
+    let v_data = render_message(
+        text,
+        image,
+        audio,
+        attribution.as_ref(),
+        &Template::default(),
+    )
+    .await?;
+    send_v_data(bot, db_conn, message, message.id, v_data).await
+}
+
+/// Download the largest size of photo attached to message
+async fn get_message_photo(bot: &Bot, message: &Message) -> Option<Vec<u8>> {
+    let size = message.photo()?.last()?;
+    download_file(bot, size.file.id.clone()).await
+}
+
+/// Meme photo with its caption, captions with `/gen` command are handled by dispatcher
+async fn handle_private_message(
+    bot: &Bot,
+    message: &Message,
+    user: &User,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    if let (Some(_), Some(caption)) = (message.photo(), message.caption()) {
+        let image = get_message_photo(bot, message).await;
+        let text = caption.trim();
+        return gen_message(bot, message, Some(user), db_conn, text, image).await;
+    }
+    Ok(())
+}
+
+async fn handle_message(
+    bot: &Bot,
+    message: &Message,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let user = message.from();
+    if message.chat.is_private() {
+        return match user {
+            Some(user) => handle_private_message(bot, message, user, db_conn).await,
+            None => Ok(()),
+        };
+    }
+    let data = match get_text(message) {
+        Some(data) if !data.is_empty() => data,
+        _ => return Ok(()),
+    };
+
+    if !message.chat.is_group() && !message.chat.is_supergroup() && !message.chat.is_channel() {
+        return Ok(());
+    }
+
+    info!("Bot received a new message: {}", data);
+
+    if let Some(reply) = message.reply_to_message() {
+        if is_quote_request(data) {
+            quote_message(bot, message, reply, db_conn).await?;
+            remember_memed(message.chat.id, message.id);
+            return Ok(());
+        }
+    }
+
+    // Commands of other bots
+    if data.starts_with('/') {
+        return Ok(());
+    }
+
+    let (chat_id, thread_id) = (message.chat.id.0, library_thread(message));
+    let settings = get_chat_settings(db_conn, chat_id, thread_id).await;
+    if !settings.triggers {
+        return Ok(());
+    }
+    let custom_words = get_custom_words_from_db(db_conn, chat_id, thread_id).await;
+    let image_words = custom_words.clone();
+    let audio_words = custom_words.clone();
+    let image_handler = get_image(bot, message, user, db_conn, image_words, data);
+    let audio_handler = get_custom_content(db_conn, chat_id, thread_id, audio_words, data, false);
+    let attribution = if settings.attribution {
+        message_attribution(message)
+    } else {
+        None
+    };
+    match build_message(
+        data,
+        custom_words,
+        image_handler,
+        audio_handler,
+        attribution,
+    )
+    .await?
+    {
+        Some(v_data) => send_v_data(bot, db_conn, message, message.id, v_data).await,
+        None => Ok(()),
+    }
+}
+
+/// Execute command which passed permission checks of dispatcher
+async fn exec_command(
+    bot: &Bot,
+    message: &Message,
+    command: Command,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    match command {
+        Command::Help | Command::Start => {
+            let key = if message.chat.is_private() {
+                PRIVATE_HELP_KEY
+            } else {
+                GROUP_HELP_KEY
+            };
+            bot.send_message(message.chat.id, TEXTS.get_tg(key, message))
+                .reply_to_message_id(message.id)
+                .await?;
+        }
+        Command::Version => {
+            bot.send_message(message.chat.id, VERSION_STRING)
+                .reply_to_message_id(message.id)
+                .await?;
+        }
+        Command::Quote => match message.reply_to_message() {
+            Some(reply) => quote_message(bot, message, reply, db_conn).await?,
+            None => {
+                bot.send_message(message.chat.id, TEXTS.get_tg("quote_no_reply", message))
+                    .reply_to_message_id(message.id)
+                    .await?;
+            }
+        },
+        Command::Gen(text) => {
+            let image = get_message_photo(bot, message).await;
+            gen_message(bot, message, message.from(), db_conn, text.trim(), image).await?;
+        }
+        command => exec_content_command(bot, message, command, db_conn).await?,
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "db"))]
+async fn exec_content_command(
+    bot: &Bot,
+    msg: &Message,
+    _command: Command,
+    _db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    bot.send_message(msg.chat.id, TEXTS.get_tg(GROUP_HELP_KEY, msg))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+#[cfg(feature = "db")]
+async fn exec_content_command(
+    bot: &Bot,
+    message: &Message,
+    command: Command,
+    db_conn: Option<&DBConn>,
+) -> Result<(), HandlerError> {
+    let db_conn = db_conn.ok_or(HandlerError::Config(
+        "DB connection is not available".into(),
+    ))?;
+
+    async fn get_words(bot: &Bot, msg: &Message, db_conn: &DBConn) -> Result<(), HandlerError> {
+        let resp = db_conn
+            .get_words(msg.chat.id.0, library_thread(msg))
+            .await?;
+        if resp.is_empty() {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("empty_list_message", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, resp)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn rm_content(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+        is_image: bool,
+    ) -> Result<(), HandlerError> {
+        let args = args.trim();
+        if args.is_empty() {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("invalid_arguments", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Err(HandlerError::InvalidCommand(String::from("Args invalid")));
+        }
+        match db_conn
+            .rm_content(
+                msg.chat.id.0,
+                library_thread(msg),
+                is_image,
+                String::from(args),
+            )
+            .await
+        {
+            Ok(_) => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg("rm_content_success", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+            }
+            Err(_) => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg("rm_content_error", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn add_media(
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+        words: String,
+        kind: ContentKind,
+    ) -> Result<(), HandlerError> {
+        let (format_error, success) = match kind {
+            ContentKind::Image => ("image_add_format_error", "image_add_success"),
+            ContentKind::Audio => ("audio_add_format_error", "audio_add_success"),
+        };
+        let media = match find_media(msg, kind) {
+            Some(media) => media,
+            None => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg(format_error, msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Err(HandlerError::InvalidCommand(String::from(
+                    "Invalid document",
+                )));
+            }
+        };
+        let thread_id = library_thread(msg);
+        match store_media(bot, db_conn, msg.chat.id, thread_id, words, &media, kind).await {
+            Ok(_) => {}
+            Err(MediaError::Db(err)) => return Err(err),
+            Err(err) => {
+                let lang = msg
+                    .from()
+                    .and_then(|user| user.language_code.as_deref())
+                    .unwrap_or("en");
+                bot.send_message(msg.chat.id, media_error_text(&err, kind, lang))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Err(err.into());
+            }
+        }
+        bot.send_message(msg.chat.id, TEXTS.get_tg(success, msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_content(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+        is_image: bool,
+    ) -> Result<(), HandlerError> {
+        let args = args.trim();
+        if args.is_empty() {
+            return start_flow(bot, msg, db_conn, ContentKind::from_is_image(is_image)).await;
+        }
+        if !WORDS_REGEX.is_match(args) {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("keyword_error", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Err(HandlerError::InvalidCommand(String::from(
+                "Invalid keywords",
+            )));
+        }
+        add_media(
+            bot,
+            msg,
+            db_conn,
+            String::from(args),
+            ContentKind::from_is_image(is_image),
+        )
+        .await
+    }
+
+    async fn change_words(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+        is_image: bool,
+    ) -> Result<(), HandlerError> {
+        let args = args.trim();
+        if args.is_empty() {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("invalid_arguments", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Err(HandlerError::InvalidCommand(String::from("Args invalid")));
+        }
+        if let Some(match_args) = CHANGE_WORDS_REGEX.captures(args) {
+            let file_name = match_args
+                .get(1)
+                .map(|data| data.as_str())
+                .or_else(|| Some(""))
+                .unwrap();
+            let new_words = match_args
+                .get(2)
+                .map(|data| data.as_str())
+                .or_else(|| Some(""))
+                .unwrap();
+            db_conn
+                .change_words(
+                    msg.chat.id.0,
+                    library_thread(msg),
+                    is_image,
+                    String::from(file_name),
+                    String::from(new_words),
+                )
+                .await?;
+            bot.send_message(msg.chat.id, TEXTS.get_tg("done_msg", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+        bot.send_message(msg.chat.id, TEXTS.get_tg("error_msg", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Err(HandlerError::InvalidCommand(String::from("Args invalid")))
+    }
+
+    async fn export_contents(
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let (chat_id, thread_id) = (msg.chat.id.0, library_thread(msg));
+        let mut items = db_conn.get_all_contents(chat_id, thread_id, true).await?;
+        items.append(&mut db_conn.get_all_contents(chat_id, thread_id, false).await?);
+        if items.is_empty() {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("empty_list_message", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+        let archive = InputFile::memory(pack_contents(&items)?).file_name(EXPORT_FILE_NAME);
+        bot.send_document(msg.chat.id, archive)
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    async fn import_contents(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let replace = args.trim() == IMPORT_REPLACE_ARG;
+        let thread_id = library_thread(msg);
+        if let MessageKind::Common(item) = &msg.kind {
+            if let MediaKind::Document(doc) = &item.media_kind {
+                if let Some(data) = download_file(bot, doc.document.file.id.clone()).await {
+                    let lang = msg
+                        .from()
+                        .and_then(|user| user.language_code.as_deref())
+                        .unwrap_or("en");
+                    let limits = ContentLimits::new();
+                    let stats = import_stats(db_conn, msg.chat.id, thread_id, replace).await?;
+                    let items = match unpack_contents(msg.chat.id.0, data, &limits, &stats) {
+                        Ok(items) => items,
+                        Err(err) => {
+                            let text = match &err {
+                                UnpackError::Limit(err) => limit_text(err, lang),
+                                UnpackError::Format(_) => TEXTS.get_tg("import_format_error", msg),
+                            };
+                            bot.send_message(msg.chat.id, text)
+                                .reply_to_message_id(msg.id)
+                                .await?;
+                            return Err(err.into());
+                        }
+                    };
+                    let items = match prepare_import(&limits, &stats, items) {
+                        Ok(items) => items,
+                        Err(MediaError::Db(err)) => return Err(err),
+                        Err(err) => {
+                            let text = media_error_text(&err, ContentKind::Image, lang);
+                            bot.send_message(msg.chat.id, text)
+                                .reply_to_message_id(msg.id)
+                                .await?;
+                            return Err(err.into());
+                        }
+                    };
+                    let count = db_conn
+                        .import_contents(msg.chat.id.0, thread_id, items, replace)
+                        .await?;
+                    bot.send_message(
+                        msg.chat.id,
+                        format!("{} {}", TEXTS.get_tg("import_success", msg), count),
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                    return Ok(());
+                }
+                bot.send_message(msg.chat.id, TEXTS.get_tg("import_dw_error", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Err(HandlerError::Telegram("Invalid file load".into()));
+            }
+        }
+        bot.send_message(msg.chat.id, TEXTS.get_tg("import_format_error", msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Err(HandlerError::InvalidCommand(String::from(
+            "Invalid document",
+        )))
+    }
+
+    async fn set_attribution(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let mut settings = db_conn
+            .get_settings(msg.chat.id.0, library_thread(msg))
+            .await?;
+        settings.attribution = match args.trim() {
+            SETTING_ON_ARG => true,
+            SETTING_OFF_ARG => false,
+            _ => !settings.attribution,
+        };
+        db_conn.set_settings(&settings).await?;
+        let key = if settings.attribution {
+            "attribution_on"
+        } else {
+            "attribution_off"
+        };
+        bot.send_message(msg.chat.id, TEXTS.get_tg(key, msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_triggers(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let mut settings = db_conn
+            .get_settings(msg.chat.id.0, library_thread(msg))
+            .await?;
+        settings.triggers = match args.trim() {
+            SETTING_ON_ARG => true,
+            SETTING_OFF_ARG => false,
+            _ => !settings.triggers,
+        };
+        db_conn.set_settings(&settings).await?;
+        let key = if settings.triggers {
+            "triggers_on"
+        } else {
+            "triggers_off"
+        };
+        bot.send_message(msg.chat.id, TEXTS.get_tg(key, msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_verbosity(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let mut settings = db_conn
+            .get_settings(msg.chat.id.0, library_thread(msg))
+            .await?;
+        let text = match args.parse() {
+            Ok(verbosity) => {
+                settings.verbosity = verbosity;
+                db_conn.set_settings(&settings).await?;
+                format!("{} {}", TEXTS.get_tg("errors_set", msg), verbosity.as_str())
+            }
+            Err(_) => format!(
+                "{} {}",
+                TEXTS.get_tg("errors_usage", msg),
+                settings.verbosity.as_str()
+            ),
+        };
+        bot.send_message(msg.chat.id, text)
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    /// Get user to change role of: author of replied message or user id from arguments
+    fn get_role_target(msg: &Message, args: &str) -> Option<(i64, String)> {
+        if let Some(user) = msg.reply_to_message().and_then(|reply| reply.from()) {
+            if user.is_bot {
+                return None;
+            }
+            return Some((user.id.0 as i64, user.full_name()));
+        }
+        args.trim().parse().ok().map(|id: i64| (id, id.to_string()))
+    }
+
+    async fn grant_contributor(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let (user_id, name) = match get_role_target(msg, &args) {
+            Some(target) => target,
+            None => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg("role_no_target", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Err(HandlerError::InvalidCommand(String::from(
+                    "No user to grant role",
+                )));
+            }
+        };
+        db_conn
+            .add_contributor(&Contributor {
+                chat_id: msg.chat.id.0,
+                user_id,
+                name: name.clone(),
+            })
+            .await?;
+        bot.send_message(
+            msg.chat.id,
+            format!("{} {}", TEXTS.get_tg("grant_success", msg), name),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_contributor(
+        args: String,
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let (user_id, _) = match get_role_target(msg, &args) {
+            Some(target) => target,
+            None => {
+                bot.send_message(msg.chat.id, TEXTS.get_tg("role_no_target", msg))
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Err(HandlerError::InvalidCommand(String::from(
+                    "No user to revoke role",
+                )));
+            }
+        };
+        let key = match db_conn.rm_contributor(msg.chat.id.0, user_id).await {
+            Ok(_) => "revoke_success",
+            Err(_) => "revoke_error",
+        };
+        bot.send_message(msg.chat.id, TEXTS.get_tg(key, msg))
+            .reply_to_message_id(msg.id)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_contributors(
+        bot: &Bot,
+        msg: &Message,
+        db_conn: &DBConn,
+    ) -> Result<(), HandlerError> {
+        let items = db_conn.get_contributors(msg.chat.id.0).await?;
+        if items.is_empty() {
+            bot.send_message(msg.chat.id, TEXTS.get_tg("empty_list_message", msg))
+                .reply_to_message_id(msg.id)
+                .await?;
+        } else {
+            let resp = items
+                .iter()
+                .map(|i| format!("{} - {}", i.name, i.user_id))
+                .collect::<Vec<String>>()
+                .join("\n");
+            bot.send_message(msg.chat.id, resp)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    match command {
+        Command::ListImage => send_browser(bot, message, db_conn, ContentKind::Image).await?,
+        Command::ListAudio => send_browser(bot, message, db_conn, ContentKind::Audio).await?,
+        Command::ListWords => get_words(bot, message, db_conn).await?,
+        Command::AddImage(args) => add_content(args, bot, message, db_conn, true).await?,
+        Command::AddAudio(args) => add_content(args, bot, message, db_conn, false).await?,
+        Command::RmImage(args) => rm_content(args, bot, message, db_conn, true).await?,
+        Command::RmAudio(args) => rm_content(args, bot, message, db_conn, false).await?,
+        Command::EditImage(args) => change_words(args, bot, message, db_conn, true).await?,
+        Command::EditAudio(args) => change_words(args, bot, message, db_conn, false).await?,
+        Command::Export => export_contents(bot, message, db_conn).await?,
+        Command::Import(args) => import_contents(args, bot, message, db_conn).await?,
+        Command::Attribution(args) => set_attribution(args, bot, message, db_conn).await?,
+        Command::Errors(args) => set_verbosity(args, bot, message, db_conn).await?,
+        Command::Triggers(args) => set_triggers(args, bot, message, db_conn).await?,
+        Command::Grant(args) => grant_contributor(args, bot, message, db_conn).await?,
+        Command::Revoke(args) => revoke_contributor(args, bot, message, db_conn).await?,
+        Command::Contributors => get_contributors(bot, message, db_conn).await?,
+        Command::Cancel => cancel_flow(bot, message, db_conn).await?,
+        command => {
+            return Err(HandlerError::InvalidCommand(format!(
+                "Command is not a content command: {:?}",
+                command
+            )))
+        }
+    };
+    Ok(())
+}
+
+impl Locale {
+    /// Get localized string with unwrap lang code from teloxide message
+    ///
+    /// Parameters:
+    ///  - key: localization key
+    ///  - msg: message instance from teloxide message handler
+    ///
+    /// Return: localized string or key
+    fn get_tg(&self, key: &str, msg: &Message) -> String {
+        let lang = &msg
+            .from()
+            .and_then(|u| u.language_code.clone())
+            .unwrap_or(String::from("en"));
+        self.get(&*format!("tg_{}", key), lang.as_str())
+    }
+}
@@ -0,0 +1,145 @@
+//! Command permissions
+//!
+//! Every command requires a role. Anyone in chat is a member; contributors are granted per chat
+//! by admins with `/grant`; admins are chat administrators and everyone in own private chat;
+//! bot owner from `BOT_OWNER_ID` may run any command anywhere. Chat administrators are cached
+//! and kept up to date with `ChatMemberUpdated` events, cache entries also expire after
+//! `ADMIN_CACHE_TTL` seconds in case some events were missed.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use teloxide::prelude::*;
+use teloxide::types::{Chat, ChatMemberUpdated, User};
+use teloxide::Bot;
+
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+
+const BOT_OWNER_ID_KEY: &str = "BOT_OWNER_ID";
+const ADMIN_CACHE_TTL_KEY: &str = "ADMIN_CACHE_TTL";
+
+lazy_static! {
+    static ref BOT_OWNER_ID: Option<UserId> = std::env::var(BOT_OWNER_ID_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .map(UserId);
+    static ref ADMIN_CACHE_TTL: u64 = std::env::var(ADMIN_CACHE_TTL_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(3600);
+    static ref ADMIN_CACHE: RwLock<HashMap<ChatId, (Instant, HashSet<UserId>)>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Role of user in chat, every role has permissions of lower ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Member,
+    Contributor,
+    Admin,
+    Owner,
+}
+
+/// Get role of message sender in message chat
+///
+/// Messages sent on behalf of the chat itself are written by anonymous admins, so are all
+/// posts in channels.
+///
+/// Parameters:
+///  - bot: bot instance to fetch chat administrators
+///  - message: message of the user
+///  - db_conn: database connection to look up contributors
+///
+/// Return: the highest role of the user
+pub(super) async fn get_role(bot: &Bot, message: &Message, db_conn: Option<&DBConn>) -> Role {
+    match message.from() {
+        Some(user) if !user.is_anonymous() => {
+            get_user_role(bot, &message.chat, user, db_conn).await
+        }
+        _ => match message.sender_chat() {
+            Some(chat) if chat.id == message.chat.id => Role::Admin,
+            _ => Role::Member,
+        },
+    }
+}
+
+/// Get role of user in chat, failed lookups are logged and don't grant the role
+///
+/// Parameters:
+///  - bot: bot instance to fetch chat administrators
+///  - chat: chat to check role in
+///  - user: user to check
+///  - db_conn: database connection to look up contributors
+///
+/// Return: the highest role of the user
+pub(super) async fn get_user_role(
+    bot: &Bot,
+    chat: &Chat,
+    user: &User,
+    db_conn: Option<&DBConn>,
+) -> Role {
+    if Some(user.id) == *BOT_OWNER_ID {
+        return Role::Owner;
+    }
+    if chat.is_private() {
+        return Role::Admin;
+    }
+    match is_admin(bot, chat.id, user.id).await {
+        Ok(true) => return Role::Admin,
+        Ok(false) => {}
+        Err(e) => error!("{:?}", e),
+    }
+    if let Some(db_conn) = db_conn {
+        match db_conn.is_contributor(chat.id.0, user.id.0 as i64).await {
+            Ok(true) => return Role::Contributor,
+            Ok(false) => {}
+            Err(e) => error!("{:?}", e),
+        }
+    }
+    Role::Member
+}
+
+async fn is_admin(bot: &Bot, chat_id: ChatId, user_id: UserId) -> Result<bool, HandlerError> {
+    if let Some((cached_at, admins)) = ADMIN_CACHE.read().unwrap().get(&chat_id) {
+        if cached_at.elapsed() < Duration::from_secs(*ADMIN_CACHE_TTL) {
+            return Ok(admins.contains(&user_id));
+        }
+    }
+    let admins: HashSet<UserId> = bot
+        .get_chat_administrators(chat_id)
+        .await?
+        .into_iter()
+        .map(|member| member.user.id)
+        .collect();
+    let is_admin = admins.contains(&user_id);
+    ADMIN_CACHE
+        .write()
+        .unwrap()
+        .insert(chat_id, (Instant::now(), admins));
+    Ok(is_admin)
+}
+
+/// Keep cached chat administrators up to date with member status changes
+pub(super) async fn chat_member_handler(update: ChatMemberUpdated) -> ResponseResult<()> {
+    let member = &update.new_chat_member;
+    if let Some((_, admins)) = ADMIN_CACHE.write().unwrap().get_mut(&update.chat.id) {
+        if member.is_privileged() {
+            admins.insert(member.user.id);
+        } else {
+            admins.remove(&member.user.id);
+        }
+        info!("Admins of chat {} are updated.", update.chat.id);
+    }
+    Ok(())
+}
+
+/// Forget cached chat administrators when status of bot itself changes
+///
+/// Bot gets `chat_member` updates only being an admin, so cache may be stale after demotion.
+pub(super) fn forget_admins(chat_id: ChatId) {
+    ADMIN_CACHE.write().unwrap().remove(&chat_id);
+}
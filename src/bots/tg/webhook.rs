@@ -0,0 +1,73 @@
+//! Webhook mode
+//!
+//! Bot uses long polling unless `WEBHOOK_URL` is set. In webhook mode an embedded HTTP server
+//! listens on `WEBHOOK_ADDRESS` for updates Telegram sends to the public `WEBHOOK_URL`, e.g.
+//! behind a reverse proxy. Requests without `WEBHOOK_SECRET` in the
+//! `X-Telegram-Bot-Api-Secret-Token` header are rejected, random secret is used if it is not set.
+//! Webhook is removed when the dispatcher stops.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use reqwest::Url;
+use teloxide::dispatching::update_listeners::webhooks::{self, Options};
+use teloxide::dispatching::update_listeners::UpdateListener;
+use teloxide::Bot;
+
+const WEBHOOK_URL_KEY: &str = "WEBHOOK_URL";
+const WEBHOOK_ADDRESS_KEY: &str = "WEBHOOK_ADDRESS";
+const WEBHOOK_SECRET_KEY: &str = "WEBHOOK_SECRET";
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0:8080";
+
+lazy_static! {
+    static ref WEBHOOK_URL: Option<Url> = std::env::var(WEBHOOK_URL_KEY)
+        .ok()
+        .filter(|i| !i.is_empty())
+        .and_then(|i| match i.parse() {
+            Ok(url) => Some(url),
+            Err(e) => {
+                error!("Invalid {}: {:?}", WEBHOOK_URL_KEY, e);
+                None
+            }
+        });
+    static ref WEBHOOK_ADDRESS: SocketAddr = std::env::var(WEBHOOK_ADDRESS_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(DEFAULT_ADDRESS.parse().unwrap());
+    static ref WEBHOOK_SECRET: Option<String> = std::env::var(WEBHOOK_SECRET_KEY)
+        .ok()
+        .filter(|i| !i.is_empty());
+}
+
+/// Webhook options from env
+///
+/// Return: options or `None` if bot should use long polling
+pub(super) fn webhook_options() -> Option<Options> {
+    let mut options = Options::new(*WEBHOOK_ADDRESS, WEBHOOK_URL.clone()?);
+    if let Some(secret) = WEBHOOK_SECRET.clone() {
+        options = options.secret_token(secret);
+    }
+    Some(options)
+}
+
+/// Set webhook and start HTTP server receiving updates
+///
+/// Parameters:
+///  - bot: bot instance
+///  - options: webhook options
+///
+/// Return: update listener for dispatcher
+///
+/// Panics if the address can not be bound or webhook can not be set, bot can't work without it.
+pub async fn webhook_listener(bot: Bot, options: Options) -> impl UpdateListener<Err = Infallible> {
+    info!(
+        "Listening for webhook {} on {}.",
+        options.url, options.address
+    );
+    webhooks::axum(bot, options)
+        .await
+        .expect("Failed to set webhook")
+}
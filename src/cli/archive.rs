@@ -0,0 +1,42 @@
+use crate::models::content_archive::{pack_contents, unpack_contents};
+use crate::models::content_limits::ContentLimits;
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use std::path::PathBuf;
+
+pub async fn export(
+    db_conn: &DBConn,
+    chat_id: i64,
+    thread_id: i64,
+    out: PathBuf,
+) -> Result<(), HandlerError> {
+    let mut items = db_conn.get_all_contents(chat_id, thread_id, true).await?;
+    items.append(&mut db_conn.get_all_contents(chat_id, thread_id, false).await?);
+    std::fs::write(&out, pack_contents(&items)?)?;
+    println!("Exported {} items to {}", items.len(), out.display());
+    Ok(())
+}
+
+pub async fn import(
+    db_conn: &DBConn,
+    chat_id: i64,
+    thread_id: i64,
+    file: PathBuf,
+    replace: bool,
+) -> Result<(), HandlerError> {
+    let stats = match replace {
+        true => db_conn.get_chat_stats_without(chat_id, thread_id).await?,
+        false => db_conn.get_chat_stats(chat_id).await?,
+    };
+    let items = unpack_contents(
+        chat_id,
+        std::fs::read(&file)?,
+        &ContentLimits::new(),
+        &stats,
+    )?;
+    let count = db_conn
+        .import_contents(chat_id, thread_id, items, replace)
+        .await?;
+    println!("Imported {} items into {}", count, chat_id);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use crate::models::content_limits::ContentLimits;
+use crate::models::content_model::{ContentKind, ContentModel};
+use crate::models::db_conn::DBConn;
+use crate::models::error::HandlerError;
+use chrono::{Duration, Utc};
+use image::ImageFormat;
+use std::path::PathBuf;
+
+pub async fn migrate(db_conn: &DBConn) -> Result<(), HandlerError> {
+    db_conn.migrate().await?;
+    println!("Database is up to date");
+    Ok(())
+}
+
+pub async fn list_chats(db_conn: &DBConn) -> Result<(), HandlerError> {
+    for chat in db_conn.get_chats().await? {
+        println!(
+            "{}\timages: {}\taudio: {}\tsize: {}",
+            chat.chat_id, chat.images, chat.audio, chat.size
+        );
+    }
+    Ok(())
+}
+
+pub async fn list_content(
+    db_conn: &DBConn,
+    chat_id: i64,
+    thread_id: i64,
+    kind: Option<ContentKind>,
+) -> Result<(), HandlerError> {
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None => vec![ContentKind::Image, ContentKind::Audio],
+    };
+    for kind in kinds {
+        for item in db_conn
+            .get_all_contents(chat_id, thread_id, kind.is_image())
+            .await?
+        {
+            println!(
+                "{:?}\t{}\t{}\tsize: {}",
+                kind,
+                item.name,
+                item.words,
+                item.data.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn add_content(
+    db_conn: &DBConn,
+    chat_id: i64,
+    thread_id: i64,
+    kind: ContentKind,
+    words: String,
+    file: PathBuf,
+) -> Result<(), HandlerError> {
+    let data = std::fs::read(&file)?;
+    if kind.is_image() && image::guess_format(&data)? != ImageFormat::Jpeg {
+        return Err(HandlerError::InvalidCommand(String::from(
+            "Image must be JPEG",
+        )));
+    }
+    let name = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(HandlerError::InvalidCommand(String::from(
+            "Invalid file name",
+        )))?
+        .to_string();
+    let item = ContentModel {
+        thread_id,
+        ..ContentModel::from(chat_id, kind.is_image(), words, name, data)
+    };
+    if item.words.is_empty() {
+        return Err(HandlerError::InvalidCommand(String::from(
+            "Trigger words are empty",
+        )));
+    }
+    println!("Adding {:?} '{}' into {}", kind, item.name, chat_id);
+    Ok(db_conn.add_content(item, &ContentLimits::new()).await?)
+}
+
+pub async fn rm_content(
+    db_conn: &DBConn,
+    chat_id: i64,
+    thread_id: i64,
+    kind: ContentKind,
+    name: String,
+) -> Result<(), HandlerError> {
+    db_conn
+        .rm_content(chat_id, thread_id, kind.is_image(), name)
+        .await?;
+    println!("Content removed");
+    Ok(())
+}
+
+pub async fn vacuum(db_conn: &DBConn) -> Result<(), HandlerError> {
+    db_conn.vacuum().await?;
+    println!("Database vacuumed");
+    Ok(())
+}
+
+pub async fn purge_chats(db_conn: &DBConn, days: i64) -> Result<(), HandlerError> {
+    let removed_before = (Utc::now() - Duration::days(days)).timestamp();
+    let chat_ids = db_conn.purge_removed_chats(removed_before).await?;
+    for chat_id in &chat_ids {
+        println!("{}", chat_id);
+    }
+    println!("Purged chats: {}", chat_ids.len());
+    Ok(())
+}
+
+pub async fn stats(db_conn: &DBConn) -> Result<(), HandlerError> {
+    let chats = db_conn.get_chats().await?;
+    println!("chats: {}", chats.len());
+    println!("images: {}", chats.iter().map(|i| i.images).sum::<i64>());
+    println!("audio: {}", chats.iter().map(|i| i.audio).sum::<i64>());
+    println!("size: {}", chats.iter().map(|i| i.size).sum::<i64>());
+    Ok(())
+}
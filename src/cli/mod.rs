@@ -0,0 +1,98 @@
+//! Operator commandline
+//!
+//! Maintenance commands which are executed instead of starting the bot. Only commands working
+//! with contents connect to the database, they expect it to be created and migrated by the bot
+//! or by `migrate` command.
+
+#[cfg(feature = "db")]
+mod archive;
+#[cfg(feature = "db")]
+mod content;
+mod render;
+
+#[cfg(feature = "db")]
+use crate::models::db_conn::{create_db, DBConn};
+use crate::models::error::HandlerError;
+use crate::models::render_job::RenderJob;
+use crate::models::run_options::RunCommand;
+
+/// Execute operator command
+///
+/// Parameters:
+///  - command: parsed commandline subcommand
+///
+/// Return: result of void or HandlerError
+pub async fn exec_cli(command: RunCommand) -> Result<(), HandlerError> {
+    match command {
+        RunCommand::Run => Err(HandlerError::InvalidCommand(String::from(
+            "Bot is not started by operator commands",
+        ))),
+        #[cfg(feature = "db")]
+        RunCommand::Migrate => {
+            create_db().await?;
+            content::migrate(&DBConn::new().await?).await
+        }
+        #[cfg(feature = "db")]
+        RunCommand::ListChats => content::list_chats(&DBConn::new().await?).await,
+        #[cfg(feature = "db")]
+        RunCommand::ListContent { chat, thread, kind } => {
+            content::list_content(&DBConn::new().await?, chat, thread, kind).await
+        }
+        #[cfg(feature = "db")]
+        RunCommand::AddContent {
+            chat,
+            thread,
+            kind,
+            words,
+            file,
+        } => content::add_content(&DBConn::new().await?, chat, thread, kind, words, file).await,
+        #[cfg(feature = "db")]
+        RunCommand::RmContent {
+            chat,
+            thread,
+            kind,
+            name,
+        } => content::rm_content(&DBConn::new().await?, chat, thread, kind, name).await,
+        #[cfg(feature = "db")]
+        RunCommand::Vacuum => content::vacuum(&DBConn::new().await?).await,
+        #[cfg(feature = "db")]
+        RunCommand::Stats => content::stats(&DBConn::new().await?).await,
+        #[cfg(feature = "db")]
+        RunCommand::PurgeChats { days } => content::purge_chats(&DBConn::new().await?, days).await,
+        RunCommand::Render {
+            batch: Some(batch), ..
+        } => render::render_batch(batch).await,
+        RunCommand::Render {
+            text,
+            image,
+            audio,
+            template,
+            author,
+            format,
+            out,
+            batch: None,
+        } => {
+            render::render(RenderJob {
+                text: text.unwrap_or_default(),
+                image,
+                audio,
+                template,
+                author,
+                format,
+                out: out.unwrap_or_default(),
+            })
+            .await
+        }
+        #[cfg(feature = "db")]
+        RunCommand::Export { chat, thread, out } => {
+            archive::export(&DBConn::new().await?, chat, thread, out).await
+        }
+        #[cfg(feature = "db")]
+        RunCommand::Import {
+            chat,
+            thread,
+            file,
+            replace,
+        } => archive::import(&DBConn::new().await?, chat, thread, file, replace).await,
+    }
+}
@@ -0,0 +1,71 @@
+use crate::engine::engine::{render_image, render_message};
+use crate::models::attribution::Attribution;
+use crate::models::error::HandlerError;
+use crate::models::render_job::{RenderFormat, RenderJob};
+use crate::models::template::Template;
+use crate::models::v_data::VData::{Image, Video};
+use chrono::Utc;
+use log::error;
+use std::path::PathBuf;
+
+const IMAGE_EXTENSION: &str = "png";
+
+pub async fn render(job: RenderJob) -> Result<(), HandlerError> {
+    let template = match &job.template {
+        Some(template) => Template::resolve(template)?,
+        None => Template::default(),
+    };
+    let image = job.image.map(std::fs::read).transpose()?;
+    let audio = job.audio.map(std::fs::read).transpose()?;
+    let attribution = job
+        .author
+        .map(|author| Attribution::new(author, Some(Utc::now())));
+    let text = job.text.as_str();
+    match job.format {
+        RenderFormat::Image => {
+            std::fs::write(
+                &job.out,
+                render_image(text, image, attribution.as_ref(), &template).await?,
+            )?;
+            println!("Image saved to {}", job.out.display());
+        }
+        RenderFormat::Video => {
+            match render_message(text, image, audio, attribution.as_ref(), &template).await? {
+                Video(video) => {
+                    std::fs::write(&job.out, video)?;
+                    println!("Video saved to {}", job.out.display());
+                }
+                Image(image) => {
+                    let out = job.out.with_extension(IMAGE_EXTENSION);
+                    std::fs::write(&out, image)?;
+                    println!("Video encoding failed, image saved to {}", out.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn render_batch(batch: PathBuf) -> Result<(), HandlerError> {
+    let content = std::fs::read_to_string(&batch)?;
+    let mut failed: usize = 0;
+    for (ind, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match serde_json::from_str::<RenderJob>(line) {
+            Ok(job) => render(job).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            error!("Job at line {} failed: {:?}", ind + 1, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(HandlerError::Render(
+            format!("{} jobs failed", failed).into(),
+        ));
+    }
+    Ok(())
+}
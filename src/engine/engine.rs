@@ -8,8 +8,7 @@ use std::io::{BufWriter, Cursor};
 use std::str;
 use std::time::Duration;
 
-use image::{ColorType, EncodableLayout, GenericImageView, ImageFormat, Rgb, RgbImage};
-use imageproc::definitions::HasWhite;
+use image::{ColorType, EncodableLayout, GenericImageView, ImageEncoder, ImageFormat, Rgb, RgbImage};
 use imageproc::drawing::{draw_text, Canvas};
 use lazy_static::lazy_static;
 use log::{debug, error, info};
@@ -20,7 +19,9 @@ use rusttype::{Font, Scale};
 use crate::engine::default_images::get_rand_image;
 use crate::engine::engine::VData::{Image, Video};
 use crate::engine::local_ffmpeg::{check_ffmpeg_exist, encode_video_local};
+use crate::models::attribution::Attribution;
 use crate::models::error::HandlerError;
+use crate::models::template::{AttributionPosition, Template};
 use crate::models::text_size_box::TextSizeBox;
 use crate::models::v_data::VData;
 use crate::utils::size_utils::aspect_resize;
@@ -31,15 +32,9 @@ use std::future::Future;
 
 const WORDS_KEY: &str = "WORDS";
 const CONVERTER_URL_KEY: &str = "CONVERTER_URL";
-const IMAGE_SIZE: u32 = 1024;
-const PHOTO_W: u32 = 768;
-const PHOTO_H: u32 = 512;
-const PADDING: u32 = 128;
-const STRING_BATCH_SIZE: usize = 30;
-const MAX_ROWS: usize = 6;
-const FONT_H: u32 = 50;
 
 const FONT_BYTES: &[u8] = include_bytes!("../../assets/font.ttf");
+const ATTRIBUTION_MARGIN: u32 = 24;
 
 lazy_static! {
     static ref REGEX_VALUE: Regex = regex::Regex::new("/gen (.*)").unwrap();
@@ -52,7 +47,6 @@ lazy_static! {
         .map(|i| i.to_string())
         .collect::<Vec<String>>();
     static ref CLIENT: Client = reqwest::Client::new();
-    static ref FONT_SIZE: Scale = Scale::uniform(64.0);
 }
 
 /// Create meme-quote if needs with optional image and audio
@@ -62,20 +56,22 @@ lazy_static! {
 ///  - custom_words:    optional trigger words
 ///  - image_handler:   async closure that returns an optional binary image
 ///  - audio_handler:   async closure that returns an optional binary audio
+///  - attribution:     optional author of message drawn under text
 ///
-/// Return: Result with VData or HandlerError
+/// Return: Result with VData, `None` if text has no trigger words, or HandlerError
 pub async fn build_message(
     res: &str,
     custom_words: Option<String>,
     image_handler: impl Future<Output = Option<Vec<u8>>>,
     audio_handler: impl Future<Output = Option<Vec<u8>>>,
-) -> Result<VData, HandlerError> {
+    attribution: Option<Attribution>,
+) -> Result<Option<VData>, HandlerError> {
     let message: &str;
     if let Some(words) = custom_words {
         info!("Found trigger words in DB: {:?}", words);
         let words = contains_in(words, String::from(res));
         if words.is_empty() {
-            return Err(HandlerError::empty());
+            return Ok(None);
         }
         info!("Trigger words: {:?}", words);
         message = res;
@@ -85,26 +81,51 @@ pub async fn build_message(
             .iter()
             .fold(false, |acc, item| acc || res.contains(item))
         {
-            return Err(HandlerError::empty());
+            return Ok(None);
         }
         message = res;
     } else {
         info!("Trigger words not found. Use '/gen <message>' checker.");
-        let match_res = REGEX_VALUE
-            .captures(res)
-            .ok_or(HandlerError::new(String::from("Invalid command")))?
+        let captures = match REGEX_VALUE.captures(res) {
+            Some(captures) => captures,
+            None => return Ok(None),
+        };
+        message = captures
             .get(1)
             .map(|data| data.as_str())
             .unwrap_or("");
-        message = match_res;
-    }
-    let mut input_image: Vec<u8> = get_rand_image();
-    if let Some(user_image) = image_handler.await {
-        input_image = user_image;
     }
-    let image = create_image(message, input_image.clone()).await?;
-    let custom_audio = audio_handler.await;
-    match encode_video(image.clone(), custom_audio).await {
+    let input_image = image_handler.await;
+    render_message(
+        message,
+        input_image,
+        audio_handler.await,
+        attribution.as_ref(),
+        &Template::default(),
+    )
+    .await
+    .map(Some)
+}
+
+/// Create meme-quote without checking for trigger words
+///
+/// Parameters:
+///  - message: text of meme
+///  - image:   optional binary JPEG image, random default image is used if none
+///  - audio:   optional binary audio
+///  - attribution: optional author of message drawn under text
+///  - template: layout of image
+///
+/// Return: Result with VData (image if video encoding failed) or HandlerError
+pub async fn render_message(
+    message: &str,
+    image: Option<Vec<u8>>,
+    audio: Option<Vec<u8>>,
+    attribution: Option<&Attribution>,
+    template: &Template,
+) -> Result<VData, HandlerError> {
+    let image = render_image(message, image, attribution, template).await?;
+    match encode_video(image.clone(), audio).await {
         Ok(video) => Ok(Video(video)),
         Err(e) => {
             error!("error encoding video {:?}", e);
@@ -113,21 +134,52 @@ pub async fn build_message(
     }
 }
 
-async fn create_image(message: &str, input: Vec<u8>) -> Result<Vec<u8>, HandlerError> {
+/// Create meme-quote image without video encoding
+///
+/// Parameters:
+///  - message: text of meme
+///  - image:   optional binary JPEG image, random default image is used if none
+///  - attribution: optional author of message drawn under text
+///  - template: layout of image
+///
+/// Return: Result with binary PNG image or HandlerError
+pub async fn render_image(
+    message: &str,
+    image: Option<Vec<u8>>,
+    attribution: Option<&Attribution>,
+    template: &Template,
+) -> Result<Vec<u8>, HandlerError> {
+    create_image(
+        message,
+        image.unwrap_or_else(get_rand_image),
+        attribution,
+        template,
+    )
+    .await
+}
+
+async fn create_image(
+    message: &str,
+    input: Vec<u8>,
+    attribution: Option<&Attribution>,
+    template: &Template,
+) -> Result<Vec<u8>, HandlerError> {
     let font = match Font::try_from_vec(Vec::from(FONT_BYTES)) {
         None => {
-            return Err(HandlerError::new(String::from("Can not instantiate font")));
+            return Err(HandlerError::Render("Can not instantiate font".into()));
         }
         Some(data) => data,
     };
     let reader = Cursor::new(input);
-    let start_image = image::load(reader, ImageFormat::Jpeg)?
-        .as_rgb8()
-        .unwrap()
-        .clone();
+    let start_image = image::load(reader, ImageFormat::Jpeg)?.to_rgb8();
     let mut out: Vec<u8> = Vec::new();
     let (_, _, start_image_w, start_image_h) = start_image.bounds();
-    let (new_w, new_h) = aspect_resize(start_image_w, start_image_h, PHOTO_W, PHOTO_H);
+    let (new_w, new_h) = aspect_resize(
+        start_image_w,
+        start_image_h,
+        template.photo_w,
+        template.photo_h,
+    );
     let res = image::imageops::resize(&start_image, new_w, new_h, FilterType::Gaussian);
     let cursor = BufWriter::new(&mut out);
 
@@ -135,62 +187,107 @@ async fn create_image(message: &str, input: Vec<u8>) -> Result<Vec<u8>, HandlerE
     if message.contains('\n') {
         let list: Vec<&str> = message.split("\n").collect();
         for item in list {
-            subs.append(&mut batch(item, STRING_BATCH_SIZE))
+            subs.append(&mut batch(item, template.row_length))
         }
     } else {
-        subs = batch(message, STRING_BATCH_SIZE);
+        subs = batch(message, template.row_length);
     }
 
-    let mut y = PHOTO_H + PADDING;
+    let font_size = Scale::uniform(template.font_size);
+    let mut y = template.photo_h + template.padding;
     let rect_list: Vec<TextSizeBox> = subs
         .iter()
-        .map(|msg| TextSizeBox::from(msg.as_str(), font.borrow(), *FONT_SIZE))
+        .map(|msg| TextSizeBox::from(msg.as_str(), font.borrow(), font_size))
         .collect();
+    let attribution_line = attribution.map(|i| i.line());
+    let attribution_scale = Scale::uniform(template.attribution.font_size);
+    let attribution_h = template.attribution.font_size as u32;
 
-    let (_subs, _) = if subs.len() < MAX_ROWS {
+    let (_subs, _) = if subs.len() < template.max_rows {
         (subs.as_slice(), subs.as_slice())
     } else {
-        subs.split_at(MAX_ROWS)
+        subs.split_at(template.max_rows)
     };
 
-    if _subs.len() < MAX_ROWS {
-        let h: u32 = rect_list.iter().fold(0, |sum, val| {
+    if _subs.len() < template.max_rows {
+        let mut h: u32 = rect_list.iter().fold(0, |sum, val| {
             return if sum > 0 {
                 sum + val.h + 10
             } else {
                 sum + val.h
             };
         });
-        y += ((IMAGE_SIZE - y) - h) / 2 + 10;
+        if attribution_line.is_some()
+            && template.attribution.position == AttributionPosition::BelowText
+        {
+            h += attribution_h + 10;
+        }
+        y += (template.size - y).saturating_sub(h) / 2 + 10;
     }
 
-    let mut image: RgbImage = RgbImage::new(IMAGE_SIZE, IMAGE_SIZE);
+    let mut image: RgbImage =
+        RgbImage::from_pixel(template.size, template.size, Rgb(template.background));
     for (ind, msg) in _subs.iter().enumerate() {
         let rect = rect_list.get(ind).unwrap();
         image = draw_text(
             &mut image,
-            Rgb::white(),
-            i32::try_from((IMAGE_SIZE - rect.w) / 2)?,
+            Rgb(template.text_color),
+            i32::try_from(template.size.saturating_sub(rect.w) / 2)?,
             i32::try_from(y)?,
-            *FONT_SIZE,
+            font_size,
             &font,
             msg.as_str(),
         );
-        y += FONT_H + 10;
+        y += template.line_height + 10;
+    }
+
+    if let Some(line) = attribution_line {
+        let rect = TextSizeBox::from(line.as_str(), font.borrow(), attribution_scale);
+        let centered_x = template.size.saturating_sub(rect.w) / 2;
+        let bottom_y = template
+            .size
+            .saturating_sub(attribution_h + ATTRIBUTION_MARGIN);
+        let (x, y) = match template.attribution.position {
+            AttributionPosition::BelowText => (centered_x, y.min(bottom_y)),
+            AttributionPosition::Bottom => (
+                template.size.saturating_sub(rect.w + ATTRIBUTION_MARGIN),
+                bottom_y,
+            ),
+            AttributionPosition::Top => (
+                centered_x,
+                template
+                    .padding
+                    .saturating_sub(attribution_h + ATTRIBUTION_MARGIN),
+            ),
+        };
+        image = draw_text(
+            &image,
+            Rgb(template.attribution.color),
+            i32::try_from(x)?,
+            i32::try_from(y)?,
+            attribution_scale,
+            &font,
+            line.as_str(),
+        );
     }
 
     let (_, _, img_x_stride, img_y_stride) = res.bounds();
-    let x_offset = (IMAGE_SIZE - img_x_stride) / 2;
+    let x_offset = (template.size - img_x_stride) / 2;
     let y_offset: u32;
-    if img_y_stride < PHOTO_H {
-        y_offset = PADDING + ((PHOTO_H - img_y_stride) / 2);
+    if img_y_stride < template.photo_h {
+        y_offset = template.padding + ((template.photo_h - img_y_stride) / 2);
     } else {
-        y_offset = PADDING
+        y_offset = template.padding
     };
     res.enumerate_pixels().into_iter().for_each(|px| {
         image.draw_pixel(px.0 + x_offset, px.1 + y_offset, px.2.clone());
     });
-    PngEncoder::new(cursor).encode(image.as_bytes(), IMAGE_SIZE, IMAGE_SIZE, ColorType::Rgb8)?;
+    PngEncoder::new(cursor).write_image(
+        image.as_bytes(),
+        template.size,
+        template.size,
+        ColorType::Rgb8,
+    )?;
     Ok(out)
 }
 
@@ -1,10 +1,10 @@
 use crate::models::error::HandlerError;
+use crate::utils::shutdown::{track_child, track_file, untrack_child, untrack_file};
 use log::{debug, warn};
 use std::fs::File;
 use std::io;
 use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
 use uuid::Uuid;
 
 pub fn encode_video_local(frame: Vec<u8>, audio: Option<Vec<u8>>) -> Result<Vec<u8>, HandlerError> {
@@ -21,6 +21,9 @@ pub fn encode_video_local(frame: Vec<u8>, audio: Option<Vec<u8>>) -> Result<Vec<
     safe_remove(jpg_file.as_str());
     safe_remove(mp4_file.as_str());
     safe_remove(mp3_file.as_str());
+    track_file(jpg_file.as_str());
+    track_file(mp4_file.as_str());
+    track_file(mp3_file.as_str());
 
     File::create(jpg_file.as_str())
         .expect("Unable to create image file")
@@ -59,7 +62,7 @@ pub fn encode_video_local(frame: Vec<u8>, audio: Option<Vec<u8>>) -> Result<Vec<
         "30",
         mp4_file.as_str(),
     ]);
-    let mut eval_result = eval(&*args);
+    let mut eval_result = eval_tracked(&args);
     if let Err(ref err) = eval_result {
         warn!("error occured while formatting ffmpeg: {:?}", err);
     }
@@ -71,10 +74,11 @@ pub fn encode_video_local(frame: Vec<u8>, audio: Option<Vec<u8>>) -> Result<Vec<
 
     if is_success {
         debug!("--->>> encode_video LOCAL :: success");
-        let result = std::fs::read(mp4_file.clone())?;
+        let result = std::fs::read(mp4_file.clone());
         safe_remove(jpg_file.as_str());
         safe_remove(mp4_file.as_str());
-        Ok(result)
+        safe_remove(mp3_file.as_str());
+        Ok(result?)
     } else {
         debug!("--->>> encode_video LOCAL :: error");
         if let Ok(ref data) = eval_result {
@@ -85,7 +89,8 @@ pub fn encode_video_local(frame: Vec<u8>, audio: Option<Vec<u8>>) -> Result<Vec<
         }
         safe_remove(jpg_file.as_str());
         safe_remove(mp4_file.as_str());
-        Err(HandlerError::empty())
+        safe_remove(mp3_file.as_str());
+        Err(HandlerError::Encode("FFMPEG exit with error".into()))
     }
 }
 
@@ -104,9 +109,37 @@ fn eval(args: &[&str]) -> io::Result<Output> {
     };
 }
 
+/// Run command like `eval`, process is killed if bot shuts down before it exits
+fn eval_tracked(args: &[&str]) -> io::Result<Output> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.arg("/C").args(args);
+        command
+    } else {
+        // `exec` replaces shell, so it is the process to kill
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("exec {}", args.join(" ")));
+        command
+    };
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    track_child(pid);
+    let output = child.wait_with_output();
+    untrack_child(pid);
+    output
+}
+
 fn safe_remove(file_name: &str) {
-    if Path::new(file_name).exists() {
-        debug!("--->>> encode_video LOCAL :: remove file {}", file_name);
-        std::fs::remove_file(file_name).unwrap();
+    untrack_file(file_name);
+    debug!("--->>> encode_video LOCAL :: remove file {}", file_name);
+    // File may be never created or already removed by shutdown cleanup
+    if let Err(e) = std::fs::remove_file(file_name) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Can't remove file {}: {}", file_name, e);
+        }
     }
 }
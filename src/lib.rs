@@ -1,20 +1,24 @@
-use crate::models::db_conn::setup_db;
-use crate::models::run_options::RunOptions;
+use crate::cli::exec_cli;
+use crate::models::db_conn::{setup_db, DBConn};
+use crate::models::error::HandlerError;
+use crate::models::run_options::{RunCommand, RunOptions};
 use crate::utils::logger::setup_logger;
+use log::error;
 
 pub mod bots;
+pub mod cli;
 pub mod engine;
 pub mod models;
 pub mod utils;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "tg")] {
-        async fn run() {
+        async fn run(db_conn: Option<DBConn>) {
             use crate::bots::tg::run_tg_bot;
-            run_tg_bot().await;
+            run_tg_bot(db_conn).await;
         }
     } else {
-        async fn run() {
+        async fn run(_db_conn: Option<DBConn>) {
             println!("No selected feature");
         }
     }
@@ -22,7 +26,27 @@ cfg_if::cfg_if! {
 
 pub async fn start() {
     dotenv::dotenv().ok();
-    setup_logger(&RunOptions::new()).unwrap();
-    setup_db().await.unwrap();
-    run().await;
+    let options = RunOptions::new();
+    if let Err(e) = setup_logger(&options) {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+    let result = match options.command.unwrap_or(RunCommand::Run) {
+        RunCommand::Run => start_bot().await,
+        command => exec_cli(command).await,
+    };
+    if let Err(e) = result {
+        error!("{:?}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Prepare database and run bot until it is stopped
+async fn start_bot() -> Result<(), HandlerError> {
+    let db_conn = setup_db().await?;
+    run(db_conn.clone()).await;
+    if let Some(db_conn) = db_conn {
+        db_conn.close().await;
+    }
+    Ok(())
 }
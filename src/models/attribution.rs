@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+
+const DATE_FORMAT: &str = "%-d %b %Y";
+
+/// Author of quoted message shown under meme text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribution {
+    /// `@username` or display name of author
+    pub author: String,
+    pub date: Option<DateTime<Utc>>,
+}
+
+impl Attribution {
+    pub fn new(author: String, date: Option<DateTime<Utc>>) -> Self {
+        Self { author, date }
+    }
+
+    /// Text of attribution line, like "— @username, 17 Oct 2026"
+    pub fn line(&self) -> String {
+        match self.date {
+            Some(date) => format!("— {}, {}", self.author, date.format(DATE_FORMAT)),
+            None => format!("— {}", self.author),
+        }
+    }
+}
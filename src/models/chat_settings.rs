@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+
+use crate::models::error::HandlerError;
+
+const ATTRIBUTION_KEY: &str = "ATTRIBUTION";
+const ERROR_VERBOSITY_KEY: &str = "ERROR_VERBOSITY";
+
+lazy_static! {
+    static ref ATTRIBUTION: bool = std::env::var(ATTRIBUTION_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(false);
+    static ref ERROR_VERBOSITY: Verbosity = std::env::var(ERROR_VERBOSITY_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(Verbosity::Brief);
+}
+
+/// How bot tells chat that meme was not made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Errors are only logged
+    Silent,
+    /// Short reply about what went wrong
+    Brief,
+    /// Reasons are added for admins, image sent instead of video is noted
+    Detailed,
+}
+
+impl Verbosity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Silent => "silent",
+            Verbosity::Brief => "brief",
+            Verbosity::Detailed => "detailed",
+        }
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = HandlerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "silent" => Ok(Verbosity::Silent),
+            "brief" => Ok(Verbosity::Brief),
+            "detailed" => Ok(Verbosity::Detailed),
+            _ => Err(HandlerError::InvalidCommand(format!(
+                "Unknown verbosity: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Per-chat bot settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Forum topic with own settings, 0 for whole chat
+    pub thread_id: i64,
+    /// Draw author name and date under meme text
+    pub attribution: bool,
+    /// Replies about failed memes
+    pub verbosity: Verbosity,
+    /// Messages with trigger words are memed, `/gen` and `/quote` work anyway
+    pub triggers: bool,
+}
+
+impl ChatSettings {
+    /// Settings of chat which has never changed them, defaults are taken from env
+    pub fn new(chat_id: i64, thread_id: i64) -> Self {
+        Self {
+            chat_id,
+            thread_id,
+            attribution: *ATTRIBUTION,
+            verbosity: *ERROR_VERBOSITY,
+            triggers: true,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+/// Content statistics of single chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatStats {
+    pub chat_id: i64,
+    pub images: i64,
+    pub audio: i64,
+    /// Total size of content data in bytes
+    pub size: i64,
+}
@@ -0,0 +1,185 @@
+//! Chat content archive
+//!
+//! ZIP file with `manifest.json` (names, kinds and trigger words) and media files,
+//! used to back up a chat library or move it into another chat. Archives are checked against
+//! content limits while they are unpacked: item count before any file is decompressed, file
+//! and total sizes by bytes actually read, not by sizes archive declares.
+
+use crate::models::chat_stats::ChatStats;
+use crate::models::content_limits::{ContentLimits, LimitError};
+use crate::models::content_model::{ContentKind, ContentModel};
+use crate::models::error::HandlerError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+const MAX_MANIFEST_SIZE: i64 = 4 * 1024 * 1024;
+
+fn kind_dir(kind: ContentKind) -> &'static str {
+    match kind {
+        ContentKind::Image => "images",
+        ContentKind::Audio => "audio",
+    }
+}
+
+/// Reason archive can not be unpacked
+#[derive(Debug)]
+pub enum UnpackError {
+    /// Archive does not fit chat limits
+    Limit(LimitError),
+    /// Archive is damaged or is not an exported library
+    Format(HandlerError),
+}
+
+impl From<UnpackError> for HandlerError {
+    fn from(err: UnpackError) -> Self {
+        match err {
+            UnpackError::Limit(err) => {
+                HandlerError::InvalidCommand(format!("Limit exceeded: {:?}", err))
+            }
+            UnpackError::Format(err) => err,
+        }
+    }
+}
+
+impl From<HandlerError> for UnpackError {
+    fn from(err: HandlerError) -> Self {
+        UnpackError::Format(err)
+    }
+}
+
+impl From<ZipError> for UnpackError {
+    fn from(err: ZipError) -> Self {
+        UnpackError::Format(err.into())
+    }
+}
+
+impl From<serde_json::Error> for UnpackError {
+    fn from(err: serde_json::Error) -> Self {
+        UnpackError::Format(err.into())
+    }
+}
+
+/// Description of archive contents
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub version: u32,
+    pub items: Vec<ArchiveItem>,
+}
+
+/// Single content item of archive
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveItem {
+    pub name: String,
+    pub kind: ContentKind,
+    pub words: String,
+    /// Path of media file inside archive
+    pub file: String,
+}
+
+/// Pack chat contents into ZIP archive
+///
+/// Parameters:
+///  - items: contents of chat
+///
+/// Return: binary ZIP archive
+pub fn pack_contents(items: &[ContentModel]) -> Result<Vec<u8>, HandlerError> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut manifest = ArchiveManifest {
+        version: MANIFEST_VERSION,
+        items: Vec::new(),
+    };
+    for (ind, item) in items.iter().enumerate() {
+        let kind = ContentKind::from_is_image(item.is_image);
+        let file = format!("{}/{}_{}", kind_dir(kind), ind, item.name);
+        writer.start_file(file.as_str(), options)?;
+        writer.write_all(&item.data)?;
+        manifest.items.push(ArchiveItem {
+            name: item.name.clone(),
+            kind,
+            words: item.words.clone(),
+            file,
+        });
+    }
+    writer.start_file(MANIFEST_FILE, options)?;
+    writer.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Unpack contents from ZIP archive
+///
+/// Parameters:
+///  - chat_id: chat for which contents are restored
+///  - data: binary ZIP archive
+///  - limits: content limits of chat
+///  - stats: current contents of chat counted against limits
+///
+/// Return: contents ready to be inserted
+pub fn unpack_contents(
+    chat_id: i64,
+    data: Vec<u8>,
+    limits: &ContentLimits,
+    stats: &ChatStats,
+) -> Result<Vec<ContentModel>, UnpackError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let manifest: ArchiveManifest = serde_json::from_slice(
+        &read_entry(&mut archive, MANIFEST_FILE, MAX_MANIFEST_SIZE)?
+            .ok_or_else(|| HandlerError::InvalidCommand(String::from("Manifest is too big")))?,
+    )?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(UnpackError::Format(HandlerError::InvalidCommand(format!(
+            "Unsupported archive version: {}",
+            manifest.version
+        ))));
+    }
+    limits
+        .check_quota(stats, manifest.items.len() as i64, 0)
+        .map_err(UnpackError::Limit)?;
+    let mut files = HashSet::new();
+    let mut size = 0;
+    let mut result = Vec::with_capacity(manifest.items.len());
+    for item in manifest.items {
+        if !files.insert(item.file.clone()) {
+            return Err(UnpackError::Format(HandlerError::InvalidCommand(format!(
+                "Archive entry is used twice: {}",
+                item.file
+            ))));
+        }
+        let data = read_entry(&mut archive, item.file.as_str(), limits.max_file_size)?.ok_or(
+            UnpackError::Limit(LimitError::FileTooLarge(limits.max_file_size)),
+        )?;
+        size += data.len() as i64;
+        limits
+            .check_quota(stats, result.len() as i64, size)
+            .map_err(UnpackError::Limit)?;
+        result.push(ContentModel::from(
+            chat_id,
+            item.kind.is_image(),
+            item.words,
+            item.name,
+            data,
+        ));
+    }
+    Ok(result)
+}
+
+/// Read archive entry, `None` if it is larger than `max_size` bytes
+fn read_entry(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    name: &str,
+    max_size: i64,
+) -> Result<Option<Vec<u8>>, HandlerError> {
+    let file = archive.by_name(name)?;
+    let mut out: Vec<u8> = Vec::new();
+    file.take(max_size as u64 + 1).read_to_end(&mut out)?;
+    if out.len() as i64 > max_size {
+        return Ok(None);
+    }
+    Ok(Some(out))
+}
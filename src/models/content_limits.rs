@@ -0,0 +1,116 @@
+use crate::models::chat_stats::ChatStats;
+use crate::models::content_model::ContentKind;
+use lazy_static::lazy_static;
+
+const CONTENT_MAX_ITEMS_KEY: &str = "CONTENT_MAX_ITEMS";
+const CONTENT_MAX_CHAT_MB_KEY: &str = "CONTENT_MAX_CHAT_MB";
+const CONTENT_MAX_FILE_MB_KEY: &str = "CONTENT_MAX_FILE_MB";
+const IMAGE_MAX_SIDE_KEY: &str = "IMAGE_MAX_SIDE";
+const AUDIO_MAX_DURATION_KEY: &str = "AUDIO_MAX_DURATION";
+
+const MB: i64 = 1024 * 1024;
+
+lazy_static! {
+    static ref CONTENT_MAX_ITEMS: i64 = std::env::var(CONTENT_MAX_ITEMS_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(500);
+    static ref CONTENT_MAX_CHAT_MB: i64 = std::env::var(CONTENT_MAX_CHAT_MB_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(200);
+    static ref CONTENT_MAX_FILE_MB: i64 = std::env::var(CONTENT_MAX_FILE_MB_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(10);
+    static ref IMAGE_MAX_SIDE: u32 = std::env::var(IMAGE_MAX_SIDE_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(1280);
+    static ref AUDIO_MAX_DURATION: u32 = std::env::var(AUDIO_MAX_DURATION_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(120);
+}
+
+/// Limits of contents uploaded into chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentLimits {
+    /// Number of items of all kinds in chat
+    pub max_items: i64,
+    /// Total size of chat contents in bytes
+    pub max_chat_size: i64,
+    /// Size of single uploaded file in bytes
+    pub max_file_size: i64,
+    /// Larger images are downscaled to fit this width and height
+    pub image_max_side: u32,
+    /// Duration of audio in seconds
+    pub audio_max_duration: u32,
+}
+
+/// Exceeded limit with its value
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    TooManyItems(i64),
+    ChatTooLarge(i64),
+    FileTooLarge(i64),
+    AudioTooLong(u32),
+}
+
+impl ContentLimits {
+    /// Limits taken from env, they are the same for every chat
+    pub fn new() -> Self {
+        Self {
+            max_items: *CONTENT_MAX_ITEMS,
+            max_chat_size: *CONTENT_MAX_CHAT_MB * MB,
+            max_file_size: *CONTENT_MAX_FILE_MB * MB,
+            image_max_side: *IMAGE_MAX_SIDE,
+            audio_max_duration: *AUDIO_MAX_DURATION,
+        }
+    }
+
+    /// Check file before download
+    ///
+    /// Parameters:
+    ///  - kind: kind of content
+    ///  - size: file size in bytes
+    ///  - duration: audio duration in seconds if known
+    pub fn check_file(
+        &self,
+        kind: ContentKind,
+        size: i64,
+        duration: Option<u32>,
+    ) -> Result<(), LimitError> {
+        if size > self.max_file_size {
+            return Err(LimitError::FileTooLarge(self.max_file_size));
+        }
+        match (kind, duration) {
+            (ContentKind::Audio, Some(duration)) if duration > self.audio_max_duration => {
+                Err(LimitError::AudioTooLong(self.audio_max_duration))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that chat can take new items
+    ///
+    /// Parameters:
+    ///  - stats: current contents of chat
+    ///  - items: number of new items
+    ///  - size: total size of new items in bytes
+    pub fn check_quota(&self, stats: &ChatStats, items: i64, size: i64) -> Result<(), LimitError> {
+        if stats.images + stats.audio + items > self.max_items {
+            return Err(LimitError::TooManyItems(self.max_items));
+        }
+        if stats.size + size > self.max_chat_size {
+            return Err(LimitError::ChatTooLarge(self.max_chat_size));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ContentLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,35 @@
 use crate::utils::string_utils::normalize_words;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Kind of content item
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+    Image,
+    Audio,
+}
+
+impl ContentKind {
+    pub fn from_is_image(is_image: bool) -> Self {
+        if is_image {
+            ContentKind::Image
+        } else {
+            ContentKind::Audio
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        *self == ContentKind::Image
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ContentModel {
     pub id: i64,
     pub chat_id: i64,
+    /// Forum topic of content library, 0 for whole chat
+    pub thread_id: i64,
     pub is_image: bool,
     pub words: String,
     pub name: String,
@@ -15,6 +41,7 @@ impl ContentModel {
         Self {
             id: 0,
             chat_id,
+            thread_id: 0,
             is_image,
             words: normalize_words(words),
             name: name.replace(" ", "_").trim().to_lowercase(),
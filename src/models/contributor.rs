@@ -0,0 +1,8 @@
+/// Chat member allowed to add contents without being a chat admin
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contributor {
+    pub chat_id: i64,
+    pub user_id: i64,
+    /// Display name at the moment of granting, used in lists only
+    pub name: String,
+}
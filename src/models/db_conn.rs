@@ -1,88 +1,296 @@
+use crate::models::chat_settings::ChatSettings;
 use crate::models::content_model::ContentModel;
 use crate::models::error::HandlerError;
-use lazy_static::lazy_static;
 
 #[cfg(feature = "db")]
 use {
+    crate::models::chat_stats::ChatStats,
+    crate::models::content_limits::{ContentLimits, LimitError},
+    crate::models::contributor::Contributor,
     crate::utils::string_utils::normalize_words,
+    lazy_static::lazy_static,
+    log::info,
     rand::seq::SliceRandom,
     sqlx::migrate::MigrateDatabase,
-    sqlx::sqlite::SqliteConnectOptions,
-    sqlx::sqlite::SqlitePoolOptions,
     sqlx::ConnectOptions,
-    sqlx::{Pool, Sqlite},
-    std::collections::HashSet,
+    sqlx::Pool,
+    std::collections::{HashMap, HashSet},
     std::str::FromStr,
-    std::time::Duration,
+    std::sync::{Arc, RwLock},
+    std::time::{Duration, Instant},
 };
 
+#[cfg(all(feature = "db", not(feature = "postgres")))]
+use {
+    sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    sqlx::Sqlite,
+};
+
+#[cfg(feature = "postgres")]
+use {
+    sqlx::postgres::{PgConnectOptions, PgPoolOptions},
+    sqlx::Postgres,
+};
+
+/// Database backend selected by features
+#[cfg(all(feature = "db", not(feature = "postgres")))]
+type DB = Sqlite;
+#[cfg(feature = "postgres")]
+type DB = Postgres;
+
+#[cfg(all(feature = "db", not(feature = "postgres")))]
+const DEFAULT_DB_URL: &str = "sqlite:data.db";
+#[cfg(feature = "postgres")]
+const DEFAULT_DB_URL: &str = "postgres://localhost/why_do_you_bot";
+
+#[cfg(feature = "db")]
+const DB_URL_KEY: &str = "DATABASE_URL";
+#[cfg(feature = "db")]
+const DB_BUSY_TIMEOUT_KEY: &str = "DATABASE_BUSY_TIMEOUT";
+#[cfg(feature = "db")]
+const DB_MAX_CONNECTIONS_KEY: &str = "DATABASE_MAX_CONNECTIONS";
+#[cfg(feature = "db")]
+const DB_WORDS_CACHE_TTL_KEY: &str = "DATABASE_WORDS_CACHE_TTL";
+
+#[cfg(feature = "db")]
 lazy_static! {
-    static ref DB_URL: String =
-        std::env::var("DATABASE_URL").unwrap_or(String::from("sqlite:data.db"));
+    static ref DB_URL: String = std::env::var(DB_URL_KEY).unwrap_or(String::from(DEFAULT_DB_URL));
+    static ref DB_BUSY_TIMEOUT: u64 = std::env::var(DB_BUSY_TIMEOUT_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(5);
+    static ref DB_MAX_CONNECTIONS: u32 = std::env::var(DB_MAX_CONNECTIONS_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(10);
+    static ref DB_WORDS_CACHE_TTL: u64 = std::env::var(DB_WORDS_CACHE_TTL_KEY)
+        .ok()
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(60);
 }
 
+/// Cached trigger words of chat topic
+///
+/// Generation is bumped on every invalidation, so words queried before contents were changed
+/// are not cached over the new ones.
+#[cfg(feature = "db")]
+#[derive(Default)]
+struct CachedWords {
+    generation: u64,
+    words: Option<(Instant, String)>,
+}
+
+/// Trigger words of chat topics
+#[cfg(feature = "db")]
+type WordsCache = HashMap<(i64, i64), CachedWords>;
+
 /// Database connection wrapper
+///
+/// Holds a shared pool and a per-chat cache of trigger words, so it is created once
+/// at startup and cloned into handlers. Cached words expire after `DATABASE_WORDS_CACHE_TTL`
+/// seconds, so replicas sharing one database pick up each other's changes.
+///
+/// Contents and settings are scoped by chat and forum topic (`thread_id`), 0 is the whole chat.
 /// TODO: Expand this for another bot implementation or divide implementations
+#[derive(Clone)]
 pub struct DBConn {
     #[cfg(feature = "db")]
-    pool: Pool<Sqlite>,
+    pool: Pool<DB>,
+    #[cfg(feature = "db")]
+    words_cache: Arc<RwLock<WordsCache>>,
+}
+
+/// Reason content item can not be added
+#[cfg(feature = "db")]
+#[derive(Debug)]
+pub enum StoreError {
+    /// Chat has no room for item
+    Limit(LimitError),
+    Db(HandlerError),
+}
+
+#[cfg(feature = "db")]
+impl From<StoreError> for HandlerError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::Limit(err) => {
+                HandlerError::InvalidCommand(format!("Limit exceeded: {:?}", err))
+            }
+            StoreError::Db(err) => err,
+        }
+    }
 }
 
+#[cfg(feature = "db")]
+impl From<LimitError> for StoreError {
+    fn from(err: LimitError) -> Self {
+        StoreError::Limit(err)
+    }
+}
+
+#[cfg(feature = "db")]
+impl From<sqlx::Error> for StoreError {
+    fn from(err: sqlx::Error) -> Self {
+        StoreError::Db(err.into())
+    }
+}
+
+/// Prepare database for work
+///
+/// Return: shared connection if DB feature is enabled
 #[cfg(not(feature = "db"))]
-pub async fn setup_db() -> Result<(), HandlerError> {
-    Ok(())
+pub async fn setup_db() -> Result<Option<DBConn>, HandlerError> {
+    Ok(None)
 }
 
+/// Prepare database for work: create it if needed and run migrations
+///
+/// Return: shared connection if DB feature is enabled
 #[cfg(feature = "db")]
-pub async fn setup_db() -> Result<(), HandlerError> {
-    if !Sqlite::database_exists(&**DB_URL).await? {
-        Sqlite::create_database(&**DB_URL).await?;
+pub async fn setup_db() -> Result<Option<DBConn>, HandlerError> {
+    create_db().await?;
+    let db_conn = DBConn::new().await?;
+    db_conn.migrate().await?;
+    Ok(Some(db_conn))
+}
+
+/// Create database if it does not exist, it is left without tables until migrated
+#[cfg(feature = "db")]
+pub async fn create_db() -> Result<(), HandlerError> {
+    if !DB::database_exists(&DB_URL).await? {
+        DB::create_database(&DB_URL).await?;
     }
-    DBConn::new().await?.migrate().await?;
     Ok(())
 }
 
+#[cfg(all(feature = "db", not(feature = "postgres")))]
+async fn connect() -> Result<Pool<DB>, HandlerError> {
+    let mut connection_options = SqliteConnectOptions::from_str(&DB_URL)?
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(*DB_BUSY_TIMEOUT));
+    connection_options
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Warn, Duration::from_secs(1));
+    Ok(SqlitePoolOptions::new()
+        .max_connections(*DB_MAX_CONNECTIONS)
+        .connect_with(connection_options)
+        .await?)
+}
+
+#[cfg(feature = "postgres")]
+async fn connect() -> Result<Pool<DB>, HandlerError> {
+    let mut connection_options = PgConnectOptions::from_str(&DB_URL)?;
+    connection_options
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Warn, Duration::from_secs(1));
+    Ok(PgPoolOptions::new()
+        .max_connections(*DB_MAX_CONNECTIONS)
+        .acquire_timeout(Duration::from_secs(*DB_BUSY_TIMEOUT))
+        .connect_with(connection_options)
+        .await?)
+}
+
 #[cfg(feature = "db")]
 impl DBConn {
     pub async fn new() -> Result<Self, HandlerError> {
-        let mut connection_options = SqliteConnectOptions::from_str(&**DB_URL)?;
-        connection_options
-            .log_statements(log::LevelFilter::Debug)
-            .log_slow_statements(log::LevelFilter::Warn, Duration::from_secs(1));
-        let pool: Pool<Sqlite> = SqlitePoolOptions::new()
-            .connect_with(connection_options)
-            .await?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool: connect().await?,
+            words_cache: Arc::new(RwLock::new(HashMap::new())),
+        })
     }
 
+    #[cfg(not(feature = "postgres"))]
     pub async fn migrate(&self) -> Result<(), HandlerError> {
-        sqlx::migrate!().run(&self.pool).await?;
+        sqlx::migrate!("migrations/sqlite").run(&self.pool).await?;
         Ok(())
     }
 
-    pub async fn get_words(&self, chat_id: i64) -> Result<String, HandlerError> {
+    #[cfg(feature = "postgres")]
+    pub async fn migrate(&self) -> Result<(), HandlerError> {
+        sqlx::migrate!("migrations/postgres")
+            .run(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Close all pool connections, waits for acquired ones to be returned
+    pub async fn close(&self) {
+        self.pool.close().await;
+        info!("Database connections are closed.");
+    }
+
+    /// Get comma separated trigger words of all chat contents
+    ///
+    /// Result is cached per chat topic until any of its contents is changed or cache expires.
+    pub async fn get_words(&self, chat_id: i64, thread_id: i64) -> Result<String, HandlerError> {
+        let generation = {
+            let mut cache = self.words_cache.write().unwrap();
+            let cached = cache.entry((chat_id, thread_id)).or_default();
+            if let Some((cached_at, words)) = &cached.words {
+                if cached_at.elapsed() < Duration::from_secs(*DB_WORDS_CACHE_TTL) {
+                    return Ok(words.clone());
+                }
+            }
+            cached.generation
+        };
         struct PrivateWords {
             words: String,
         }
         let items: Vec<PrivateWords> = sqlx::query_as!(
             PrivateWords,
-            "SELECT words FROM contents WHERE chat_id = ?",
-            chat_id
+            "SELECT words FROM contents WHERE chat_id = $1 AND thread_id = $2",
+            chat_id,
+            thread_id
         )
         .fetch_all(&self.pool)
         .await?;
-        Ok(items
+        let words = items
             .iter()
             .map(|i| i.words.clone())
             .collect::<HashSet<String>>()
             .into_iter()
             .collect::<Vec<String>>()
-            .join(","))
+            .join(",");
+        // Contents may be changed while querying, their words are queried next time
+        if let Some(cached) = self
+            .words_cache
+            .write()
+            .unwrap()
+            .get_mut(&(chat_id, thread_id))
+        {
+            if cached.generation == generation {
+                cached.words = Some((Instant::now(), words.clone()));
+            }
+        }
+        Ok(words)
+    }
+
+    /// Forget cached words of all topics of chats
+    fn invalidate_chats(&self, chat_ids: &[i64]) {
+        for ((chat_id, _), cached) in self.words_cache.write().unwrap().iter_mut() {
+            if chat_ids.contains(chat_id) {
+                cached.generation += 1;
+                cached.words = None;
+            }
+        }
+    }
+
+    fn invalidate_words(&self, chat_id: i64, thread_id: i64) {
+        if let Some(cached) = self
+            .words_cache
+            .write()
+            .unwrap()
+            .get_mut(&(chat_id, thread_id))
+        {
+            cached.generation += 1;
+            cached.words = None;
+        }
     }
 
     pub async fn get_random_content(
         &self,
         chat_id: i64,
+        thread_id: i64,
         is_image: bool,
         words: Vec<String>,
     ) -> Result<ContentModel, HandlerError> {
@@ -90,8 +298,9 @@ impl DBConn {
         for word in words {
             let regex_word: String = format!("%{}%", word);
             let query = sqlx::query!(
-                "SELECT * FROM contents WHERE chat_id == ? AND is_image == ? AND words LIKE ? LIMIT 1",
+                "SELECT * FROM contents WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3 AND words LIKE $4 LIMIT 1",
                 chat_id,
+                thread_id,
                 is_image,
                 regex_word
             );
@@ -99,6 +308,7 @@ impl DBConn {
                 buff.push(ContentModel {
                     id: item.id,
                     chat_id: item.chat_id,
+                    thread_id: item.thread_id,
                     is_image: item.is_image,
                     words: item.words,
                     name: item.name,
@@ -108,97 +318,682 @@ impl DBConn {
         }
         buff.choose(&mut rand::thread_rng())
             .and_then(|i| Some(i.clone()))
-            .ok_or(HandlerError::empty())
+            .ok_or(HandlerError::Storage("No contents".into()))
     }
 
     pub async fn get_all_contents(
         &self,
         chat_id: i64,
+        thread_id: i64,
         is_image: bool,
     ) -> Result<Vec<ContentModel>, HandlerError> {
         Ok(sqlx::query_as!(
             ContentModel,
-            "SELECT * FROM contents WHERE chat_id == ? AND is_image == ?",
+            "SELECT * FROM contents WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3",
             chat_id,
+            thread_id,
             is_image
         )
         .fetch_all(&self.pool)
         .await?)
     }
 
-    pub async fn add_content(&self, item: ContentModel) -> Result<(), HandlerError> {
+    pub async fn count_contents(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        is_image: bool,
+    ) -> Result<i64, HandlerError> {
+        Ok(sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!: i64" FROM contents
+            WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3"#,
+            chat_id,
+            thread_id,
+            is_image
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .count)
+    }
+
+    /// Get content by its position in chat contents of kind sorted by name
+    pub async fn get_content_at(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        is_image: bool,
+        index: i64,
+    ) -> Result<Option<ContentModel>, HandlerError> {
+        Ok(sqlx::query_as!(
+            ContentModel,
+            r#"SELECT id AS "id!", chat_id AS "chat_id!", thread_id AS "thread_id!",
+            is_image AS "is_image!", name AS "name!", words AS "words!", data AS "data!"
+            FROM contents WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3
+            ORDER BY name, id LIMIT 1 OFFSET $4"#,
+            chat_id,
+            thread_id,
+            is_image,
+            index
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    pub async fn get_content(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        id: i64,
+    ) -> Result<Option<ContentModel>, HandlerError> {
+        Ok(sqlx::query_as!(
+            ContentModel,
+            "SELECT * FROM contents WHERE chat_id = $1 AND thread_id = $2 AND id = $3",
+            chat_id,
+            thread_id,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Add content item if chat stays within limits
+    ///
+    /// Quota is checked in the insert transaction, so concurrent uploads can't exceed it together.
+    pub async fn add_content(
+        &self,
+        item: ContentModel,
+        limits: &ContentLimits,
+    ) -> Result<(), StoreError> {
+        let mut tx = self.pool.begin().await?;
+        // Uploads into the same chat wait for each other, SQLite locks the whole database on insert
+        #[cfg(feature = "postgres")]
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(item.chat_id)
+            .execute(&mut tx)
+            .await?;
         sqlx::query!(
-            "INSERT INTO contents (chat_id, is_image, name, words, data) VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO contents (chat_id, thread_id, is_image, name, words, data)
+            VALUES ($1, $2, $3, $4, $5, $6)",
             item.chat_id,
+            item.thread_id,
             item.is_image,
             item.name,
             item.words,
             item.data
         )
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await?;
+        let row = sqlx::query!(
+            r#"SELECT
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 1 ELSE 0 END), 0) AS BIGINT) AS "images!: i64",
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 0 ELSE 1 END), 0) AS BIGINT) AS "audio!: i64",
+            CAST(COALESCE(SUM(LENGTH(data)), 0) AS BIGINT) AS "size!: i64"
+            FROM contents WHERE chat_id = $1"#,
+            item.chat_id
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        let stats = ChatStats {
+            chat_id: item.chat_id,
+            images: row.images,
+            audio: row.audio,
+            size: row.size,
+        };
+        // Stats already count the item, transaction is rolled back on error
+        limits.check_quota(&stats, 0, 0)?;
+        tx.commit().await?;
+        self.invalidate_words(item.chat_id, item.thread_id);
         Ok(())
     }
 
     pub async fn rm_content(
         &self,
         chat_id: i64,
+        thread_id: i64,
         is_image: bool,
         name: String,
     ) -> Result<(), HandlerError> {
-        sqlx::query!(
-            "DELETE FROM contents WHERE (chat_id, is_image, name) IN
-            (SELECT chat_id, is_image, name FROM contents WHERE chat_id == ? AND is_image == ? AND name == ?)",
+        let result = sqlx::query!(
+            "DELETE FROM contents WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3 AND name = $4",
             chat_id,
+            thread_id,
             is_image,
             name
         )
-            .execute(&self.pool)
-            .await?;
+        .execute(&self.pool)
+        .await?;
+        self.invalidate_words(chat_id, thread_id);
+        if result.rows_affected() == 0 {
+            return Err(HandlerError::Storage("Content not found".into()));
+        }
+        Ok(())
+    }
+
+    /// Remove single content item of chat topic
+    ///
+    /// Return: `false` if there is no such item
+    pub async fn rm_content_by_id(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        id: i64,
+    ) -> Result<bool, HandlerError> {
+        let result = sqlx::query!(
+            "DELETE FROM contents WHERE chat_id = $1 AND thread_id = $2 AND id = $3",
+            chat_id,
+            thread_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        self.invalidate_words(chat_id, thread_id);
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get content statistics of all chats
+    pub async fn get_chats(&self) -> Result<Vec<ChatStats>, HandlerError> {
+        Ok(sqlx::query_as!(
+            ChatStats,
+            r#"SELECT chat_id,
+            CAST(SUM(CASE WHEN is_image THEN 1 ELSE 0 END) AS BIGINT) AS "images!: i64",
+            CAST(SUM(CASE WHEN is_image THEN 0 ELSE 1 END) AS BIGINT) AS "audio!: i64",
+            CAST(SUM(LENGTH(data)) AS BIGINT) AS "size!: i64"
+            FROM contents GROUP BY chat_id ORDER BY chat_id"#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Get content statistics of single chat with all its topics, chats without contents have
+    /// zero stats
+    pub async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, HandlerError> {
+        let row = sqlx::query!(
+            r#"SELECT
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 1 ELSE 0 END), 0) AS BIGINT) AS "images!: i64",
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 0 ELSE 1 END), 0) AS BIGINT) AS "audio!: i64",
+            CAST(COALESCE(SUM(LENGTH(data)), 0) AS BIGINT) AS "size!: i64"
+            FROM contents WHERE chat_id = $1"#,
+            chat_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(ChatStats {
+            chat_id,
+            images: row.images,
+            audio: row.audio,
+            size: row.size,
+        })
+    }
+
+    /// Get content statistics of chat without contents of its topic, e.g. ones to be replaced
+    pub async fn get_chat_stats_without(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+    ) -> Result<ChatStats, HandlerError> {
+        let row = sqlx::query!(
+            r#"SELECT
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 1 ELSE 0 END), 0) AS BIGINT) AS "images!: i64",
+            CAST(COALESCE(SUM(CASE WHEN is_image THEN 0 ELSE 1 END), 0) AS BIGINT) AS "audio!: i64",
+            CAST(COALESCE(SUM(LENGTH(data)), 0) AS BIGINT) AS "size!: i64"
+            FROM contents WHERE chat_id = $1 AND thread_id <> $2"#,
+            chat_id,
+            thread_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(ChatStats {
+            chat_id,
+            images: row.images,
+            audio: row.audio,
+            size: row.size,
+        })
+    }
+
+    /// Rebuild database file to reclaim space after removing contents
+    pub async fn vacuum(&self) -> Result<(), HandlerError> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
         Ok(())
     }
 
+    /// Import contents into chat in one transaction
+    ///
+    /// Parameters:
+    ///  - chat_id: target chat
+    ///  - thread_id: target forum topic, 0 for whole chat
+    ///  - items: contents to insert
+    ///  - replace: remove all current chat contents first, otherwise items with names
+    ///    already present in chat are skipped
+    ///
+    /// Return: number of inserted items
+    pub async fn import_contents(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        items: Vec<ContentModel>,
+        replace: bool,
+    ) -> Result<usize, HandlerError> {
+        let mut tx = self.pool.begin().await?;
+        if replace {
+            sqlx::query!(
+                "DELETE FROM contents WHERE chat_id = $1 AND thread_id = $2",
+                chat_id,
+                thread_id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        let mut count: usize = 0;
+        for item in items {
+            let exists = sqlx::query!(
+                "SELECT id FROM contents WHERE chat_id = $1 AND thread_id = $2 AND is_image = $3 AND name = $4",
+                chat_id,
+                thread_id,
+                item.is_image,
+                item.name
+            )
+            .fetch_optional(&mut tx)
+            .await?
+            .is_some();
+            if exists {
+                continue;
+            }
+            sqlx::query!(
+                "INSERT INTO contents (chat_id, thread_id, is_image, name, words, data)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                chat_id,
+                thread_id,
+                item.is_image,
+                item.name,
+                item.words,
+                item.data
+            )
+            .execute(&mut tx)
+            .await?;
+            count += 1;
+        }
+        tx.commit().await?;
+        self.invalidate_words(chat_id, thread_id);
+        Ok(count)
+    }
+
     pub async fn change_words(
         &self,
         chat_id: i64,
+        thread_id: i64,
         is_image: bool,
         name: String,
         new_words: String,
     ) -> Result<(), HandlerError> {
         let words = normalize_words(new_words);
         sqlx::query!(
-            "UPDATE contents SET words = ? WHERE chat_id = ? AND is_image = ? AND name = ?",
+            "UPDATE contents SET words = $1
+            WHERE chat_id = $2 AND thread_id = $3 AND is_image = $4 AND name = $5",
             words,
             chat_id,
+            thread_id,
             is_image,
             name
         )
         .execute(&self.pool)
         .await?;
+        self.invalidate_words(chat_id, thread_id);
         Ok(())
     }
+
+    /// Change trigger words of single content item of chat topic
+    ///
+    /// Return: `false` if there is no such item
+    pub async fn change_words_by_id(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+        id: i64,
+        new_words: String,
+    ) -> Result<bool, HandlerError> {
+        let words = normalize_words(new_words);
+        let result = sqlx::query!(
+            "UPDATE contents SET words = $1 WHERE chat_id = $2 AND thread_id = $3 AND id = $4",
+            words,
+            chat_id,
+            thread_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        self.invalidate_words(chat_id, thread_id);
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get chat settings, defaults if chat has never changed them
+    pub async fn get_settings(
+        &self,
+        chat_id: i64,
+        thread_id: i64,
+    ) -> Result<ChatSettings, HandlerError> {
+        let row = sqlx::query!(
+            "SELECT attribution, verbosity, triggers FROM chat_settings
+            WHERE chat_id = $1 AND thread_id = $2",
+            chat_id,
+            thread_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let defaults = ChatSettings::new(chat_id, thread_id);
+        Ok(match row {
+            Some(row) => ChatSettings {
+                attribution: row.attribution,
+                verbosity: row.verbosity.parse().unwrap_or(defaults.verbosity),
+                triggers: row.triggers,
+                ..defaults
+            },
+            None => defaults,
+        })
+    }
+
+    pub async fn set_settings(&self, settings: &ChatSettings) -> Result<(), HandlerError> {
+        let verbosity = settings.verbosity.as_str();
+        sqlx::query!(
+            "INSERT INTO chat_settings (chat_id, thread_id, attribution, verbosity, triggers)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (chat_id, thread_id) DO UPDATE
+            SET attribution = excluded.attribution, verbosity = excluded.verbosity,
+            triggers = excluded.triggers",
+            settings.chat_id,
+            settings.thread_id,
+            settings.attribution,
+            verbosity,
+            settings.triggers
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Get serialized state of chat dialogue
+    pub async fn get_dialogue_state(&self, chat_id: i64) -> Result<Option<String>, HandlerError> {
+        Ok(
+            sqlx::query!("SELECT state FROM dialogues WHERE chat_id = $1", chat_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| row.state),
+        )
+    }
+
+    pub async fn set_dialogue_state(
+        &self,
+        chat_id: i64,
+        state: String,
+    ) -> Result<(), HandlerError> {
+        sqlx::query!(
+            "INSERT INTO dialogues (chat_id, state) VALUES ($1, $2)
+            ON CONFLICT (chat_id) DO UPDATE SET state = excluded.state",
+            chat_id,
+            state
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn rm_dialogue_state(&self, chat_id: i64) -> Result<(), HandlerError> {
+        let result = sqlx::query!("DELETE FROM dialogues WHERE chat_id = $1", chat_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(HandlerError::Storage("Dialogue not found".into()));
+        }
+        Ok(())
+    }
+
+    /// Move all data of group to supergroup it was upgraded to
+    ///
+    /// Data already present in the new chat is kept, conflicting settings, contributors and
+    /// dialogue of the old chat are dropped.
+    pub async fn migrate_chat(
+        &self,
+        old_chat_id: i64,
+        new_chat_id: i64,
+    ) -> Result<(), HandlerError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "UPDATE contents SET chat_id = $1 WHERE chat_id = $2",
+            new_chat_id,
+            old_chat_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE chat_settings SET chat_id = $1 WHERE chat_id = $2 AND NOT EXISTS
+            (SELECT 1 FROM chat_settings AS s WHERE s.chat_id = $1 AND s.thread_id = chat_settings.thread_id)",
+            new_chat_id,
+            old_chat_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE contributors SET chat_id = $1 WHERE chat_id = $2 AND NOT EXISTS
+            (SELECT 1 FROM contributors AS c WHERE c.chat_id = $1 AND c.user_id = contributors.user_id)",
+            new_chat_id,
+            old_chat_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE dialogues SET chat_id = $1 WHERE chat_id = $2 AND NOT EXISTS
+            (SELECT 1 FROM dialogues AS d WHERE d.chat_id = $1)",
+            new_chat_id,
+            old_chat_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!("DELETE FROM chat_settings WHERE chat_id = $1", old_chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!("DELETE FROM contributors WHERE chat_id = $1", old_chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!("DELETE FROM dialogues WHERE chat_id = $1", old_chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!("DELETE FROM removed_chats WHERE chat_id = $1", old_chat_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.invalidate_chats(&[old_chat_id, new_chat_id]);
+        Ok(())
+    }
+
+    /// Mark chat removed when bot is kicked from it or blocked, data is kept until purged
+    ///
+    /// Parameters:
+    ///  - chat_id: removed chat
+    ///  - removed_at: unix time of removal, time of the first removal is kept
+    pub async fn mark_chat_removed(
+        &self,
+        chat_id: i64,
+        removed_at: i64,
+    ) -> Result<(), HandlerError> {
+        sqlx::query!(
+            "INSERT INTO removed_chats (chat_id, removed_at) VALUES ($1, $2)
+            ON CONFLICT (chat_id) DO NOTHING",
+            chat_id,
+            removed_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Unmark chat removed when bot is back in it
+    pub async fn restore_chat(&self, chat_id: i64) -> Result<(), HandlerError> {
+        sqlx::query!("DELETE FROM removed_chats WHERE chat_id = $1", chat_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove all data of chats marked removed before time
+    ///
+    /// Parameters:
+    ///  - removed_before: unix time, chats removed later are kept
+    ///
+    /// Return: ids of purged chats
+    pub async fn purge_removed_chats(&self, removed_before: i64) -> Result<Vec<i64>, HandlerError> {
+        let mut tx = self.pool.begin().await?;
+        let chat_ids: Vec<i64> = sqlx::query!(
+            "SELECT chat_id FROM removed_chats WHERE removed_at < $1",
+            removed_before
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|row| row.chat_id)
+        .collect();
+        sqlx::query!(
+            "DELETE FROM contents WHERE chat_id IN
+            (SELECT chat_id FROM removed_chats WHERE removed_at < $1)",
+            removed_before
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM chat_settings WHERE chat_id IN
+            (SELECT chat_id FROM removed_chats WHERE removed_at < $1)",
+            removed_before
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM contributors WHERE chat_id IN
+            (SELECT chat_id FROM removed_chats WHERE removed_at < $1)",
+            removed_before
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM dialogues WHERE chat_id IN
+            (SELECT chat_id FROM removed_chats WHERE removed_at < $1)",
+            removed_before
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM removed_chats WHERE removed_at < $1",
+            removed_before
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        self.invalidate_chats(&chat_ids);
+        Ok(chat_ids)
+    }
+
+    /// Grant contributor role in chat, name of already granted user is updated
+    pub async fn add_contributor(&self, contributor: &Contributor) -> Result<(), HandlerError> {
+        sqlx::query!(
+            "INSERT INTO contributors (chat_id, user_id, name) VALUES ($1, $2, $3)
+            ON CONFLICT (chat_id, user_id) DO UPDATE SET name = excluded.name",
+            contributor.chat_id,
+            contributor.user_id,
+            contributor.name
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn rm_contributor(&self, chat_id: i64, user_id: i64) -> Result<(), HandlerError> {
+        let result = sqlx::query!(
+            "DELETE FROM contributors WHERE chat_id = $1 AND user_id = $2",
+            chat_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(HandlerError::Storage("Contributor not found".into()));
+        }
+        Ok(())
+    }
+
+    pub async fn is_contributor(&self, chat_id: i64, user_id: i64) -> Result<bool, HandlerError> {
+        Ok(sqlx::query!(
+            "SELECT user_id FROM contributors WHERE chat_id = $1 AND user_id = $2",
+            chat_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    pub async fn get_contributors(&self, chat_id: i64) -> Result<Vec<Contributor>, HandlerError> {
+        Ok(sqlx::query_as!(
+            Contributor,
+            "SELECT chat_id, user_id, name FROM contributors WHERE chat_id = $1 ORDER BY name",
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
 }
 
 #[cfg(not(feature = "db"))]
 impl DBConn {
     fn create_error() -> HandlerError {
-        HandlerError::from_str("DB feature not enabled")
+        HandlerError::Config("DB feature not enabled".into())
     }
 
     pub async fn new() -> Result<Self, HandlerError> {
         Err(DBConn::create_error())
     }
 
-    pub async fn get_words(&self, _chat_id: i64) -> Result<String, HandlerError> {
+    pub async fn close(&self) {}
+
+    pub async fn get_words(&self, _chat_id: i64, _thread_id: i64) -> Result<String, HandlerError> {
         Err(DBConn::create_error())
     }
 
     pub async fn get_random_content(
         &self,
         _chat_id: i64,
+        _thread_id: i64,
         _is_image: bool,
         _words: Vec<String>,
     ) -> Result<ContentModel, HandlerError> {
         Err(DBConn::create_error())
     }
+
+    pub async fn get_settings(
+        &self,
+        _chat_id: i64,
+        _thread_id: i64,
+    ) -> Result<ChatSettings, HandlerError> {
+        Err(DBConn::create_error())
+    }
+
+    pub async fn is_contributor(&self, _chat_id: i64, _user_id: i64) -> Result<bool, HandlerError> {
+        Err(DBConn::create_error())
+    }
+
+    pub async fn migrate_chat(
+        &self,
+        _old_chat_id: i64,
+        _new_chat_id: i64,
+    ) -> Result<(), HandlerError> {
+        Err(DBConn::create_error())
+    }
+
+    pub async fn mark_chat_removed(
+        &self,
+        _chat_id: i64,
+        _removed_at: i64,
+    ) -> Result<(), HandlerError> {
+        Err(DBConn::create_error())
+    }
+
+    pub async fn restore_chat(&self, _chat_id: i64) -> Result<(), HandlerError> {
+        Err(DBConn::create_error())
+    }
 }
@@ -1,112 +1,142 @@
 use fern::InitError;
 use image::ImageError;
 use log::SetLoggerError;
-use mime::FromStrError;
-use reqwest::Error;
 #[cfg(feature = "db")]
 use sqlx::migrate::MigrateError;
+use std::fmt::{Display, Formatter};
 use std::num::TryFromIntError;
 #[cfg(feature = "tg")]
 use teloxide::{DownloadError, RequestError};
+#[cfg(feature = "db")]
+use zip::result::ZipError;
 
-#[derive(Debug, PartialEq)]
-pub struct HandlerError {
-    pub message: Option<String>,
-}
+/// Underlying error of `HandlerError`
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync>;
 
 /// Custom error for all cases of life
-impl HandlerError {
-    pub fn empty() -> Self {
-        HandlerError { message: None }
-    }
+///
+/// Variants tell what went wrong so callers can react without parsing messages, the
+/// underlying error is kept as source. Plain texts become sources with `.into()`, e.g.
+/// `HandlerError::Storage("Content not found".into())`.
+#[derive(Debug)]
+pub enum HandlerError {
+    /// Command arguments or uploaded data are invalid
+    InvalidCommand(String),
+    /// Meme image can't be drawn
+    Render(ErrorSource),
+    /// Meme video can't be encoded
+    Encode(ErrorSource),
+    /// Database, archive or file can't be read or written
+    Storage(ErrorSource),
+    /// Bot API request failed
+    Telegram(ErrorSource),
+    /// HTTP request failed
+    Network(ErrorSource),
+    /// Environment, templates or locales are invalid
+    Config(ErrorSource),
+}
 
-    pub fn new(data: String) -> Self {
-        HandlerError {
-            message: data.into(),
+impl Display for HandlerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandlerError::InvalidCommand(e) => write!(f, "Invalid command: {}", e),
+            HandlerError::Render(e) => write!(f, "Render error: {}", e),
+            HandlerError::Encode(e) => write!(f, "Encode error: {}", e),
+            HandlerError::Storage(e) => write!(f, "Storage error: {}", e),
+            HandlerError::Telegram(e) => write!(f, "Telegram error: {}", e),
+            HandlerError::Network(e) => write!(f, "Network error: {}", e),
+            HandlerError::Config(e) => write!(f, "Config error: {}", e),
         }
     }
+}
 
-    pub fn from_str(data: &str) -> Self {
-        HandlerError {
-            message: String::from(data).into(),
+impl std::error::Error for HandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HandlerError::InvalidCommand(_) => None,
+            HandlerError::Render(e)
+            | HandlerError::Encode(e)
+            | HandlerError::Storage(e)
+            | HandlerError::Telegram(e)
+            | HandlerError::Network(e)
+            | HandlerError::Config(e) => Some(e.as_ref()),
         }
     }
 }
 
-impl<T> From<Option<T>> for HandlerError {
-    fn from(_: Option<T>) -> Self {
-        HandlerError::new(String::from("Can not get data from opt"))
+#[cfg(feature = "tg")]
+impl From<RequestError> for HandlerError {
+    fn from(e: RequestError) -> Self {
+        HandlerError::Telegram(e.into())
     }
 }
 
 #[cfg(feature = "tg")]
-impl From<RequestError> for HandlerError {
-    fn from(e: RequestError) -> Self {
-        HandlerError::new(format!("Teloxide request error: {:?}", e).to_string())
+impl From<DownloadError> for HandlerError {
+    fn from(e: DownloadError) -> Self {
+        HandlerError::Telegram(e.into())
     }
 }
 
 impl From<ImageError> for HandlerError {
     fn from(e: ImageError) -> Self {
-        HandlerError::new(format!("Image error: {:?}", e).to_string())
+        HandlerError::Render(e.into())
+    }
+}
+
+impl From<TryFromIntError> for HandlerError {
+    fn from(e: TryFromIntError) -> Self {
+        HandlerError::Render(e.into())
     }
 }
 
 #[cfg(feature = "db")]
 impl From<sqlx::Error> for HandlerError {
     fn from(e: sqlx::Error) -> Self {
-        HandlerError::new(format!("Sqlx error: {:?}", e).to_string())
+        HandlerError::Storage(e.into())
     }
 }
 
-#[cfg(feature = "tg")]
-impl From<DownloadError> for HandlerError {
-    fn from(e: DownloadError) -> Self {
-        HandlerError::new(format!("Teloxide download error: {:?}", e).to_string())
+#[cfg(feature = "db")]
+impl From<MigrateError> for HandlerError {
+    fn from(e: MigrateError) -> Self {
+        HandlerError::Storage(e.into())
     }
 }
 
-impl From<FromStrError> for HandlerError {
-    fn from(e: FromStrError) -> Self {
-        HandlerError::new(format!("Mime error: {:?}", e).to_string())
+#[cfg(feature = "db")]
+impl From<ZipError> for HandlerError {
+    fn from(e: ZipError) -> Self {
+        HandlerError::Storage(e.into())
     }
 }
 
-#[cfg(feature = "db")]
-impl From<MigrateError> for HandlerError {
-    fn from(e: MigrateError) -> Self {
-        HandlerError::new(format!("Migrate error: {:?}", e).to_string())
+impl From<serde_json::Error> for HandlerError {
+    fn from(e: serde_json::Error) -> Self {
+        HandlerError::Storage(e.into())
     }
 }
 
 impl From<std::io::Error> for HandlerError {
     fn from(e: std::io::Error) -> Self {
-        HandlerError::new(format!("IO error: {:?}", e).to_string())
+        HandlerError::Storage(e.into())
     }
 }
 
 impl From<reqwest::Error> for HandlerError {
-    fn from(e: Error) -> Self {
-        HandlerError::new(
-            format!("Network request was finished up with error: {:?}", e).to_string(),
-        )
+    fn from(e: reqwest::Error) -> Self {
+        HandlerError::Network(e.into())
     }
 }
 
-impl From<fern::InitError> for HandlerError {
+impl From<InitError> for HandlerError {
     fn from(e: InitError) -> Self {
-        HandlerError::new(format!("Logger init error: {:?}", e).to_string())
+        HandlerError::Config(e.into())
     }
 }
 
-impl From<log::SetLoggerError> for HandlerError {
+impl From<SetLoggerError> for HandlerError {
     fn from(e: SetLoggerError) -> Self {
-        HandlerError::new(format!("Logger set error: {:?}", e).to_string())
-    }
-}
-
-impl From<TryFromIntError> for HandlerError {
-    fn from(_: TryFromIntError) -> Self {
-        HandlerError::new("Can not convert value to int!!".to_string())
+        HandlerError::Config(e.into())
     }
 }
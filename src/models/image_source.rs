@@ -0,0 +1,32 @@
+/// Source of meme image, sources are tried in configured order until one returns image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageSource {
+    /// Custom chat image matched by trigger word
+    Content,
+    /// Profile photo of message sender
+    Avatar,
+    /// Photo of chat
+    ChatPhoto,
+    /// Random image shipped with bot, ends the chain
+    Stock,
+}
+
+impl ImageSource {
+    /// Default priority: custom content, sender avatar, chat photo, stock images
+    pub const DEFAULT_CHAIN: &'static str = "content,avatar,chat_photo,stock";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "content" => Some(Self::Content),
+            "avatar" => Some(Self::Avatar),
+            "chat_photo" => Some(Self::ChatPhoto),
+            "stock" => Some(Self::Stock),
+            _ => None,
+        }
+    }
+
+    /// Parse comma separated chain of source names, unknown names are skipped
+    pub fn parse_chain(chain: &str) -> Vec<Self> {
+        chain.split(',').filter_map(Self::from_name).collect()
+    }
+}
@@ -1,6 +1,16 @@
+pub mod attribution;
+pub mod chat_settings;
+pub mod chat_stats;
+#[cfg(feature = "db")]
+pub mod content_archive;
+pub mod content_limits;
 pub mod content_model;
+pub mod contributor;
 pub mod db_conn;
 pub mod error;
+pub mod image_source;
+pub mod render_job;
 pub mod run_options;
+pub mod template;
 pub mod text_size_box;
 pub mod v_data;
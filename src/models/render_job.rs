@@ -0,0 +1,30 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Output of offline rendering
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    /// MP4 video, falls back to PNG if encoding fails
+    #[default]
+    Video,
+    /// PNG image
+    Image,
+}
+
+/// Offline rendering task, single line of batch JSONL file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderJob {
+    pub text: String,
+    /// JPEG image, random default image if not set
+    pub image: Option<PathBuf>,
+    pub audio: Option<PathBuf>,
+    /// Builtin template name or path to JSON template
+    pub template: Option<String>,
+    /// Author drawn under text with current date
+    pub author: Option<String>,
+    #[serde(default)]
+    pub format: RenderFormat,
+    pub out: PathBuf,
+}
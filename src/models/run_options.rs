@@ -1,11 +1,132 @@
+#[cfg(feature = "db")]
+use crate::models::content_model::ContentKind;
+use crate::models::render_job::RenderFormat;
 use crate::utils::version::VERSION_STRING;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(version = VERSION_STRING)]
 pub struct RunOptions {
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub debug: bool,
+    #[command(subcommand)]
+    pub command: Option<RunCommand>,
+}
+
+/// Operator commands, bot is started if none is given
+#[derive(Subcommand)]
+pub enum RunCommand {
+    /// Start the bot (default)
+    Run,
+    /// Create database and apply migrations
+    #[cfg(feature = "db")]
+    Migrate,
+    /// Show chats which have custom contents
+    #[cfg(feature = "db")]
+    ListChats,
+    /// Show contents of chat
+    #[cfg(feature = "db")]
+    ListContent {
+        #[arg(long, allow_negative_numbers = true)]
+        chat: i64,
+        /// Forum topic with own library, whole chat if not set
+        #[arg(long, default_value_t = 0)]
+        thread: i64,
+        #[arg(long, value_enum)]
+        kind: Option<ContentKind>,
+    },
+    /// Add content into chat
+    #[cfg(feature = "db")]
+    AddContent {
+        #[arg(long, allow_negative_numbers = true)]
+        chat: i64,
+        /// Forum topic with own library, whole chat if not set
+        #[arg(long, default_value_t = 0)]
+        thread: i64,
+        #[arg(long, value_enum)]
+        kind: ContentKind,
+        /// Comma separated trigger words
+        #[arg(long)]
+        words: String,
+        /// JPEG image or audio file
+        file: PathBuf,
+    },
+    /// Remove content from chat by name
+    #[cfg(feature = "db")]
+    RmContent {
+        #[arg(long, allow_negative_numbers = true)]
+        chat: i64,
+        /// Forum topic with own library, whole chat if not set
+        #[arg(long, default_value_t = 0)]
+        thread: i64,
+        #[arg(long, value_enum)]
+        kind: ContentKind,
+        #[arg(long)]
+        name: String,
+    },
+    /// Reclaim database space
+    #[cfg(feature = "db")]
+    Vacuum,
+    /// Show totals of stored contents
+    #[cfg(feature = "db")]
+    Stats,
+    /// Remove all data of chats bot was removed from
+    #[cfg(feature = "db")]
+    PurgeChats {
+        /// Keep chats removed less than this many days ago
+        #[arg(long, default_value_t = 30)]
+        days: i64,
+    },
+    /// Render meme-quote into file without bot
+    Render {
+        #[arg(long, required_unless_present = "batch")]
+        text: Option<String>,
+        /// JPEG image, random default image if not set
+        #[arg(long)]
+        image: Option<PathBuf>,
+        #[arg(long)]
+        audio: Option<PathBuf>,
+        /// Builtin template name (classic, light) or path to JSON template
+        #[arg(long)]
+        template: Option<String>,
+        /// Author drawn under text with current date
+        #[arg(long)]
+        author: Option<String>,
+        #[arg(long, value_enum, default_value_t = RenderFormat::Video)]
+        format: RenderFormat,
+        /// Result file, PNG is saved next to it if video encoding failed
+        #[arg(long, required_unless_present = "batch")]
+        out: Option<PathBuf>,
+        /// JSONL file with render jobs, one per line
+        #[arg(long, conflicts_with_all = ["text", "image", "audio", "template", "author", "out"])]
+        batch: Option<PathBuf>,
+    },
+    /// Export chat contents into ZIP archive
+    #[cfg(feature = "db")]
+    Export {
+        #[arg(long, allow_negative_numbers = true)]
+        chat: i64,
+        /// Forum topic with own library, whole chat if not set
+        #[arg(long, default_value_t = 0)]
+        thread: i64,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Import chat contents from ZIP archive
+    #[cfg(feature = "db")]
+    Import {
+        #[arg(long, allow_negative_numbers = true)]
+        chat: i64,
+        /// Forum topic with own library, whole chat if not set
+        #[arg(long, default_value_t = 0)]
+        thread: i64,
+        #[arg(long)]
+        file: PathBuf,
+        /// Remove current chat contents instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
 }
 
 impl RunOptions {
@@ -0,0 +1,142 @@
+//! Meme layout templates
+
+use crate::models::error::HandlerError;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const CLASSIC: &str = "classic";
+const LIGHT: &str = "light";
+
+/// Place of attribution line on meme-quote image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributionPosition {
+    /// Centered right after text rows
+    #[default]
+    BelowText,
+    /// Right-aligned in the bottom corner of image
+    Bottom,
+    /// Centered above photo frame
+    Top,
+}
+
+/// Look of attribution line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttributionStyle {
+    pub position: AttributionPosition,
+    pub font_size: f32,
+    pub color: [u8; 3],
+}
+
+impl Default for AttributionStyle {
+    fn default() -> Self {
+        Self {
+            position: AttributionPosition::default(),
+            font_size: 40.0,
+            color: [160, 160, 160],
+        }
+    }
+}
+
+/// Layout of meme-quote image
+///
+/// Can be loaded from JSON file, missing fields are taken from classic template.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Template {
+    pub name: String,
+    /// Width and height of result image
+    pub size: u32,
+    /// Maximum size of photo frame
+    pub photo_w: u32,
+    pub photo_h: u32,
+    /// Top padding of photo frame
+    pub padding: u32,
+    /// Maximum chars in text row
+    pub row_length: usize,
+    pub max_rows: usize,
+    pub font_size: f32,
+    pub line_height: u32,
+    pub background: [u8; 3],
+    pub text_color: [u8; 3],
+    pub attribution: AttributionStyle,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self {
+            name: String::from(CLASSIC),
+            size: 1024,
+            photo_w: 768,
+            photo_h: 512,
+            padding: 128,
+            row_length: 30,
+            max_rows: 6,
+            font_size: 64.0,
+            line_height: 50,
+            background: [0, 0, 0],
+            text_color: [255, 255, 255],
+            attribution: AttributionStyle::default(),
+        }
+    }
+}
+
+impl Template {
+    /// Names of templates shipped with bot
+    pub fn builtin_names() -> Vec<&'static str> {
+        vec![CLASSIC, LIGHT]
+    }
+
+    /// Get template shipped with bot
+    ///
+    /// Parameters:
+    ///  - name: template name
+    ///
+    /// Return: template if it exists
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            CLASSIC => Some(Self::default()),
+            LIGHT => Some(Self {
+                name: String::from(LIGHT),
+                background: [255, 255, 255],
+                text_color: [0, 0, 0],
+                attribution: AttributionStyle {
+                    color: [96, 96, 96],
+                    ..AttributionStyle::default()
+                },
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Load template from JSON file
+    pub fn from_file(path: &Path) -> Result<Self, HandlerError> {
+        let template: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+        template.validate()?;
+        Ok(template)
+    }
+
+    fn validate(&self) -> Result<(), HandlerError> {
+        if self.photo_w > self.size
+            || self.padding + self.photo_h > self.size
+            || self.row_length <= 3
+            || self.max_rows == 0
+            || self.attribution.font_size <= 0.0
+        {
+            return Err(HandlerError::Config(
+                format!("Template '{}' does not fit into image", self.name).into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Find template by builtin name or JSON file path
+    pub fn resolve(name_or_path: &str) -> Result<Self, HandlerError> {
+        match Self::builtin(name_or_path) {
+            Some(template) => Ok(template),
+            None => Self::from_file(Path::new(name_or_path)),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::models::error::HandlerError;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+const JPEG_QUALITY: u8 = 90;
+
+/// Prepare uploaded image for storing: validate it, downscale and convert to JPEG expected by renderer
+///
+/// Transparent parts (e.g. of stickers) are put on white background. RGB JPEG images which fit
+/// into size are kept as is, other JPEGs (e.g. grayscale) are converted to RGB.
+///
+/// Parameters:
+///  - data: binary image
+///  - max_side: max width and height, larger images are downscaled keeping aspect ratio
+///
+/// Return: binary JPEG image or HandlerError if image can not be decoded
+pub fn normalize_image(data: Vec<u8>, max_side: u32) -> Result<Vec<u8>, HandlerError> {
+    let format = image::guess_format(&data)?;
+    let mut image = image::load_from_memory_with_format(&data, format)?;
+    let fits = image.width() <= max_side && image.height() <= max_side;
+    if format == ImageFormat::Jpeg && fits && image.as_rgb8().is_some() {
+        return Ok(data);
+    }
+    if !fits {
+        image = image.resize(max_side, max_side, FilterType::Lanczos3);
+    }
+    let mut background =
+        RgbaImage::from_pixel(image.width(), image.height(), Rgba([255, 255, 255, 255]));
+    imageops::overlay(&mut background, &image.to_rgba8(), 0, 0);
+    let mut out: Vec<u8> = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY)
+        .encode_image(&DynamicImage::ImageRgba8(background).to_rgb8())?;
+    Ok(out)
+}
@@ -51,6 +51,13 @@ impl Locale {
         return result.to_string();
     }
 
+    /// Language codes of all locales, base locale goes first
+    pub fn languages(&self) -> Vec<&str> {
+        let mut locales = self.locales.iter().collect::<Vec<&LocaleFileMeta>>();
+        locales.sort_by_key(|l| !l.is_base);
+        locales.iter().map(|l| l.lang.as_str()).collect()
+    }
+
     fn _test_keys(&self) -> Result<(), HandlerError> {
         let mut is_error = false;
         for locale in &self.locales {
@@ -81,7 +88,7 @@ impl Locale {
             });
         }
         if is_error {
-            Err(HandlerError::from_str("Locales has errors."))
+            Err(HandlerError::Config("Locales has errors.".into()))
         } else {
             Ok(())
         }
@@ -1,5 +1,7 @@
+pub mod image_utils;
 pub mod locale;
 pub mod logger;
+pub mod shutdown;
 pub mod size_utils;
 pub mod string_utils;
 pub mod version;
@@ -0,0 +1,115 @@
+//! Shutdown coordinator
+//!
+//! Renders register themselves as jobs, ffmpeg processes and temporary files they create are
+//! tracked too. On shutdown bot stops taking updates and waits for jobs up to
+//! `SHUTDOWN_TIMEOUT` seconds, then leftover processes are killed and files are removed.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+const SHUTDOWN_TIMEOUT_KEY: &str = "SHUTDOWN_TIMEOUT";
+const JOBS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    /// How long in-flight jobs may take after shutdown is requested
+    pub static ref SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(
+        std::env::var(SHUTDOWN_TIMEOUT_KEY)
+            .ok()
+            .and_then(|i| i.parse().ok())
+            .unwrap_or(30)
+    );
+    static ref CHILDREN: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    static ref TEMP_FILES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+static JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// In-flight job, finished when dropped
+#[must_use]
+pub struct Job;
+
+impl Job {
+    pub fn start() -> Self {
+        JOBS.fetch_add(1, Ordering::SeqCst);
+        Job
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        JOBS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Number of in-flight jobs
+pub fn active_jobs() -> usize {
+    JOBS.load(Ordering::SeqCst)
+}
+
+/// Wait until all in-flight jobs are finished
+pub async fn wait_jobs() {
+    while active_jobs() > 0 {
+        tokio::time::sleep(JOBS_POLL_INTERVAL).await;
+    }
+}
+
+/// Track child process to kill it on shutdown
+pub fn track_child(pid: u32) {
+    CHILDREN.lock().unwrap().insert(pid);
+}
+
+pub fn untrack_child(pid: u32) {
+    CHILDREN.lock().unwrap().remove(&pid);
+}
+
+/// Track temporary file to remove it on shutdown
+pub fn track_file(path: &str) {
+    TEMP_FILES.lock().unwrap().insert(path.to_string());
+}
+
+pub fn untrack_file(path: &str) {
+    TEMP_FILES.lock().unwrap().remove(path);
+}
+
+/// Kill tracked processes, remove tracked files and log what was left unfinished
+pub fn cleanup() {
+    let children: Vec<u32> = CHILDREN.lock().unwrap().drain().collect();
+    for pid in &children {
+        if let Err(e) = kill(*pid) {
+            warn!("Failed to kill process {}: {:?}", pid, e);
+        }
+    }
+    let files: Vec<String> = TEMP_FILES.lock().unwrap().drain().collect();
+    for file in &files {
+        if Path::new(file).exists() {
+            if let Err(e) = std::fs::remove_file(file) {
+                warn!("Failed to remove {}: {:?}", file, e);
+            }
+        }
+    }
+    info!(
+        "Shutdown: {} unfinished jobs, {} killed processes, {} removed files.",
+        active_jobs(),
+        children.len(),
+        files.len()
+    );
+}
+
+fn kill(pid: u32) -> std::io::Result<()> {
+    let pid = pid.to_string();
+    if cfg!(target_os = "windows") {
+        Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &pid])
+            .output()?;
+    } else {
+        Command::new("kill").args(["-9", &pid]).output()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::io::{Cursor, Write};
+use why_do_you_bot::models::chat_stats::ChatStats;
+use why_do_you_bot::models::content_archive::{pack_contents, unpack_contents, UnpackError};
+use why_do_you_bot::models::content_limits::{ContentLimits, LimitError};
+use why_do_you_bot::models::content_model::ContentModel;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const SOURCE_CHAT_ID: i64 = -100500;
+const TARGET_CHAT_ID: i64 = -100600;
+const KB: i64 = 1024;
+
+fn get_limits() -> ContentLimits {
+    ContentLimits {
+        max_items: 3,
+        max_chat_size: 64 * KB,
+        max_file_size: 16 * KB,
+        image_max_side: 100,
+        audio_max_duration: 60,
+    }
+}
+
+fn get_stats() -> ChatStats {
+    ChatStats {
+        chat_id: TARGET_CHAT_ID,
+        images: 0,
+        audio: 0,
+        size: 0,
+    }
+}
+
+/// Build archive from manifest items `(name, file)` and files `(path, size)` filled with zeros
+fn build_archive(items: &[(&str, &str)], files: &[(&str, usize)]) -> Vec<u8> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, size) in files {
+        writer.start_file(*path, options).unwrap();
+        writer.write_all(&vec![0; *size]).unwrap();
+    }
+    let items: Vec<String> = items
+        .iter()
+        .map(|(name, file)| {
+            format!(
+                r#"{{"name":"{}","kind":"image","words":"qqq","file":"{}"}}"#,
+                name, file
+            )
+        })
+        .collect();
+    writer.start_file("manifest.json", options).unwrap();
+    write!(writer, r#"{{"version":1,"items":[{}]}}"#, items.join(",")).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn archive_round_trip() {
+    let items = vec![
+        ContentModel::from(
+            SOURCE_CHAT_ID,
+            true,
+            String::from("qqq,www"),
+            String::from("image.jpg"),
+            vec![1, 2, 3],
+        ),
+        ContentModel::from(
+            SOURCE_CHAT_ID,
+            false,
+            String::from("eee"),
+            String::from("audio.mp3"),
+            vec![4, 5],
+        ),
+    ];
+
+    let archive = pack_contents(&items).unwrap();
+    let unpacked = unpack_contents(TARGET_CHAT_ID, archive, &get_limits(), &get_stats()).unwrap();
+
+    assert_eq!(
+        unpacked.len(),
+        items.len(),
+        "Number of items does not match."
+    );
+    for (item, restored) in items.iter().zip(unpacked.iter()) {
+        assert_eq!(restored.chat_id, TARGET_CHAT_ID, "Chat was not replaced.");
+        assert_eq!(restored.is_image, item.is_image, "Kind does not match.");
+        assert_eq!(restored.name, item.name, "Name does not match.");
+        assert_eq!(restored.words, item.words, "Words do not match.");
+        assert_eq!(restored.data, item.data, "Data does not match.");
+    }
+}
+
+#[test]
+fn archive_invalid() {
+    assert!(
+        unpack_contents(TARGET_CHAT_ID, vec![0, 1, 2], &get_limits(), &get_stats()).is_err(),
+        "Garbage was unpacked."
+    );
+}
+
+#[test]
+fn archive_limits() {
+    let (limits, stats) = (get_limits(), get_stats());
+
+    let archive = build_archive(&[("a.jpg", "a")], &[("a", 16 * KB as usize + 1)]);
+    assert!(
+        matches!(
+            unpack_contents(TARGET_CHAT_ID, archive, &limits, &stats),
+            Err(UnpackError::Limit(LimitError::FileTooLarge(_)))
+        ),
+        "Too large file was unpacked."
+    );
+
+    let items = [
+        ("a.jpg", "a"),
+        ("b.jpg", "b"),
+        ("c.jpg", "c"),
+        ("d.jpg", "d"),
+    ];
+    let archive = build_archive(&items, &[("a", 1)]);
+    assert!(
+        matches!(
+            unpack_contents(TARGET_CHAT_ID, archive, &limits, &stats),
+            Err(UnpackError::Limit(LimitError::TooManyItems(_)))
+        ),
+        "Items were not counted before unpacking."
+    );
+
+    let archive = build_archive(&[("a.jpg", "a"), ("b.jpg", "a")], &[("a", 1)]);
+    assert!(
+        matches!(
+            unpack_contents(TARGET_CHAT_ID, archive, &limits, &stats),
+            Err(UnpackError::Format(_))
+        ),
+        "File referenced twice was unpacked."
+    );
+
+    let items = [("a.jpg", "a"), ("b.jpg", "b")];
+    let archive = build_archive(&items, &[("a", 16 * KB as usize), ("b", 16 * KB as usize)]);
+    let full = ChatStats {
+        size: 40 * KB,
+        ..get_stats()
+    };
+    assert!(
+        matches!(
+            unpack_contents(TARGET_CHAT_ID, archive, &limits, &full),
+            Err(UnpackError::Limit(LimitError::ChatTooLarge(_)))
+        ),
+        "Total size was not checked."
+    );
+}
@@ -0,0 +1,63 @@
+use why_do_you_bot::models::chat_stats::ChatStats;
+use why_do_you_bot::models::content_limits::{ContentLimits, LimitError};
+use why_do_you_bot::models::content_model::ContentKind;
+
+const MB: i64 = 1024 * 1024;
+
+fn get_limits() -> ContentLimits {
+    ContentLimits {
+        max_items: 3,
+        max_chat_size: 2 * MB,
+        max_file_size: MB,
+        image_max_side: 100,
+        audio_max_duration: 60,
+    }
+}
+
+fn get_stats(items: i64, size: i64) -> ChatStats {
+    ChatStats {
+        chat_id: 1,
+        images: items,
+        audio: 0,
+        size,
+    }
+}
+
+#[test]
+fn limits_file() {
+    let limits = get_limits();
+    assert_eq!(limits.check_file(ContentKind::Image, MB, None), Ok(()));
+    assert_eq!(
+        limits.check_file(ContentKind::Image, MB + 1, None),
+        Err(LimitError::FileTooLarge(MB))
+    );
+    assert_eq!(limits.check_file(ContentKind::Audio, 1, Some(60)), Ok(()));
+    assert_eq!(
+        limits.check_file(ContentKind::Audio, 1, Some(61)),
+        Err(LimitError::AudioTooLong(60))
+    );
+    assert_eq!(
+        limits.check_file(ContentKind::Audio, 1, None),
+        Ok(()),
+        "Unknown duration must pass."
+    );
+}
+
+#[test]
+fn limits_quota() {
+    let limits = get_limits();
+    assert_eq!(limits.check_quota(&get_stats(2, MB), 1, MB), Ok(()));
+    assert_eq!(
+        limits.check_quota(&get_stats(3, 0), 1, 1),
+        Err(LimitError::TooManyItems(3))
+    );
+    assert_eq!(
+        limits.check_quota(&get_stats(0, 0), 4, 1),
+        Err(LimitError::TooManyItems(3)),
+        "Imported items must be counted."
+    );
+    assert_eq!(
+        limits.check_quota(&get_stats(1, 2 * MB), 1, 1),
+        Err(LimitError::ChatTooLarge(2 * MB))
+    );
+}
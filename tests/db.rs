@@ -1,11 +1,27 @@
 use sqlx::migrate::MigrateDatabase;
-use sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+use sqlx::Postgres as TestDB;
+#[cfg(not(feature = "postgres"))]
+use sqlx::Sqlite as TestDB;
 use std::collections::HashSet;
+use why_do_you_bot::models::chat_settings::{ChatSettings, Verbosity};
+use why_do_you_bot::models::content_limits::{ContentLimits, LimitError};
 use why_do_you_bot::models::content_model::ContentModel;
-use why_do_you_bot::models::db_conn::DBConn;
+use why_do_you_bot::models::contributor::Contributor;
+use why_do_you_bot::models::db_conn::{DBConn, StoreError};
 
-const DB_URL: &str = "sqlite:.test.db";
+/// Postgres tests expect a disposable server, e.g.:
+/// `docker run --rm -p 5432:5432 -e POSTGRES_HOST_AUTH_METHOD=trust postgres`
+/// Override it with `TEST_DATABASE_URL`.
+#[cfg(feature = "postgres")]
+const DEFAULT_DB_URL: &str = "postgres://postgres@localhost:5432/why_do_you_bot_test";
+#[cfg(not(feature = "postgres"))]
+const DEFAULT_DB_URL: &str = "sqlite:.test.db";
+const TEST_DB_URL_KEY: &str = "TEST_DATABASE_URL";
 const CHAT_ID: i64 = -100500;
+const NEW_CHAT_ID: i64 = -100100500;
+const THREAD_ID: i64 = 0;
+const TOPIC_ID: i64 = 7;
 const FIRST_ITEM_NAME: &str = "content1";
 const SECOND_ITEM_NAME: &str = "content2";
 
@@ -13,13 +29,15 @@ const TEST_WORD1: &str = "qqq";
 const TEST_WORD2: &str = "www";
 const TEST_WORD3: &str = "eee";
 const NEW_WORD: &str = "test";
+const USER_ID: i64 = 42;
 
 async fn get_db_conn() -> DBConn {
-    std::env::set_var("DATABASE_URL", &DB_URL);
-    if Sqlite::database_exists(&DB_URL).await.unwrap() {
-        Sqlite::drop_database(&DB_URL).await.unwrap()
+    let db_url = std::env::var(TEST_DB_URL_KEY).unwrap_or(DEFAULT_DB_URL.to_string());
+    std::env::set_var("DATABASE_URL", &db_url);
+    if TestDB::database_exists(&db_url).await.unwrap() {
+        TestDB::drop_database(&db_url).await.unwrap()
     }
-    Sqlite::create_database(&DB_URL).await.unwrap();
+    TestDB::create_database(&db_url).await.unwrap();
     let conn: DBConn = DBConn::new().await.unwrap();
     conn.migrate().await.unwrap();
     return conn;
@@ -46,17 +64,53 @@ async fn full_test_db() {
         ),
     ];
 
+    let limits = ContentLimits::new();
     for item in items.clone() {
-        conn.add_content(item.clone()).await.unwrap();
+        conn.add_content(item.clone(), &limits).await.unwrap();
     }
     assert_eq!(
-        conn.get_all_contents(CHAT_ID, true).await.unwrap().len(),
+        conn.get_all_contents(CHAT_ID, THREAD_ID, true)
+            .await
+            .unwrap()
+            .len(),
         items.len(),
         "Number of items does not match."
     );
 
+    // Concurrent uploads into chat with room for one item
+    let one_more = ContentLimits {
+        max_items: items.len() as i64 + 1,
+        ..ContentLimits::new()
+    };
+    let uploads: Vec<ContentModel> = ["quota1", "quota2"]
+        .iter()
+        .map(|name| ContentModel {
+            name: name.to_string(),
+            ..items[0].clone()
+        })
+        .collect();
+    let (first, second) = tokio::join!(
+        conn.add_content(uploads[0].clone(), &one_more),
+        conn.add_content(uploads[1].clone(), &one_more)
+    );
+    let rejected = [first, second]
+        .into_iter()
+        .filter(|result| matches!(result, Err(StoreError::Limit(LimitError::TooManyItems(_)))))
+        .count();
+    assert_eq!(rejected, 1, "Only one item fits chat quota.");
+    assert_eq!(
+        conn.count_contents(CHAT_ID, THREAD_ID, true).await.unwrap(),
+        items.len() as i64 + 1,
+        "Rejected item must not be stored."
+    );
+    for upload in uploads {
+        conn.rm_content(CHAT_ID, THREAD_ID, true, upload.name)
+            .await
+            .ok();
+    }
+
     let mut from_db = conn
-        .get_words(CHAT_ID)
+        .get_words(CHAT_ID, THREAD_ID)
         .await
         .unwrap()
         .split(",")
@@ -74,7 +128,7 @@ async fn full_test_db() {
     assert_eq!(from_db, sample, "Keywords don't match.");
 
     let model = conn
-        .get_random_content(CHAT_ID, true, sample)
+        .get_random_content(CHAT_ID, THREAD_ID, true, sample)
         .await
         .unwrap();
     assert!(
@@ -84,17 +138,53 @@ async fn full_test_db() {
         "Randomly obtained item was not found in list of test items."
     );
 
-    conn.rm_content(CHAT_ID, true, String::from(FIRST_ITEM_NAME))
+    assert_eq!(
+        conn.count_contents(CHAT_ID, THREAD_ID, true).await.unwrap(),
+        items.len() as i64,
+        "Number of items does not match count."
+    );
+    let second = conn
+        .get_content_at(CHAT_ID, THREAD_ID, true, 1)
         .await
+        .unwrap()
         .unwrap();
     assert_eq!(
-        conn.get_all_contents(CHAT_ID, true).await.unwrap().len(),
+        second.name, SECOND_ITEM_NAME,
+        "Items are not sorted by name."
+    );
+    assert!(conn
+        .get_content_at(CHAT_ID, THREAD_ID, true, items.len() as i64)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        conn.get_content(CHAT_ID, THREAD_ID, second.id)
+            .await
+            .unwrap(),
+        Some(second.clone()),
+        "Item by id does not match."
+    );
+    assert!(conn
+        .get_content(CHAT_ID + 1, THREAD_ID, second.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    conn.rm_content(CHAT_ID, THREAD_ID, true, String::from(FIRST_ITEM_NAME))
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.get_all_contents(CHAT_ID, THREAD_ID, true)
+            .await
+            .unwrap()
+            .len(),
         items.len() - 1,
         "New number of items does not match after deleting."
     );
 
     conn.change_words(
         CHAT_ID,
+        THREAD_ID,
         true,
         String::from(SECOND_ITEM_NAME),
         NEW_WORD.to_string(),
@@ -102,8 +192,253 @@ async fn full_test_db() {
     .await
     .unwrap();
     assert_eq!(
-        conn.get_words(CHAT_ID).await.unwrap(),
+        conn.get_words(CHAT_ID, THREAD_ID).await.unwrap(),
         NEW_WORD.to_string(),
         "Edited keywords don't match."
     );
+
+    assert_eq!(
+        conn.import_contents(CHAT_ID, THREAD_ID, items.clone(), false)
+            .await
+            .unwrap(),
+        1,
+        "Merge import must skip existing items."
+    );
+    assert_eq!(
+        conn.import_contents(CHAT_ID, THREAD_ID, items.clone(), true)
+            .await
+            .unwrap(),
+        items.len(),
+        "Replace import must insert all items."
+    );
+    assert_eq!(
+        conn.get_all_contents(CHAT_ID, THREAD_ID, true)
+            .await
+            .unwrap()
+            .len(),
+        items.len(),
+        "Number of items does not match after import."
+    );
+
+    conn.add_content(
+        ContentModel::from(
+            CHAT_ID,
+            true,
+            TEST_WORD1.to_string(),
+            FIRST_ITEM_NAME.to_string(),
+            Vec::new(),
+        ),
+        &limits,
+    )
+    .await
+    .unwrap();
+    let namesakes: Vec<ContentModel> = conn
+        .get_all_contents(CHAT_ID, THREAD_ID, true)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|item| item.name == FIRST_ITEM_NAME)
+        .collect();
+    assert_eq!(namesakes.len(), 2, "Items with same name are not added.");
+    let (kept, removed) = (&namesakes[0], &namesakes[1]);
+    assert!(conn
+        .change_words_by_id(CHAT_ID, THREAD_ID, removed.id, NEW_WORD.to_string())
+        .await
+        .unwrap());
+    assert_eq!(
+        conn.get_content(CHAT_ID, THREAD_ID, kept.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .words,
+        kept.words,
+        "Item with same name was edited."
+    );
+    assert!(!conn
+        .rm_content_by_id(CHAT_ID + 1, THREAD_ID, removed.id)
+        .await
+        .unwrap());
+    assert!(conn
+        .rm_content_by_id(CHAT_ID, THREAD_ID, removed.id)
+        .await
+        .unwrap());
+    assert!(!conn
+        .change_words_by_id(CHAT_ID, THREAD_ID, removed.id, NEW_WORD.to_string())
+        .await
+        .unwrap());
+    assert!(
+        conn.get_content(CHAT_ID, THREAD_ID, kept.id)
+            .await
+            .unwrap()
+            .is_some(),
+        "Item with same name was removed."
+    );
+
+    let chats = conn.get_chats().await.unwrap();
+    assert_eq!(chats.len(), 1, "Number of chats does not match.");
+    assert_eq!(
+        (chats[0].chat_id, chats[0].images, chats[0].audio),
+        (CHAT_ID, items.len() as i64, 0),
+        "Chat stats don't match."
+    );
+    assert_eq!(
+        conn.get_chat_stats(CHAT_ID).await.unwrap(),
+        chats[0],
+        "Single chat stats don't match."
+    );
+    assert_eq!(
+        conn.get_chat_stats(CHAT_ID + 1).await.unwrap().size,
+        0,
+        "Empty chat must have zero stats."
+    );
+
+    let mut settings = conn.get_settings(CHAT_ID, THREAD_ID).await.unwrap();
+    assert_eq!(
+        settings,
+        ChatSettings::new(CHAT_ID, THREAD_ID),
+        "Default settings don't match."
+    );
+    settings.attribution = !settings.attribution;
+    settings.verbosity = Verbosity::Detailed;
+    settings.triggers = false;
+    conn.set_settings(&settings).await.unwrap();
+    conn.set_settings(&settings).await.unwrap();
+    assert_eq!(
+        conn.get_settings(CHAT_ID, THREAD_ID).await.unwrap(),
+        settings,
+        "Saved settings don't match."
+    );
+
+    conn.add_content(
+        ContentModel {
+            thread_id: TOPIC_ID,
+            ..items[0].clone()
+        },
+        &limits,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        conn.get_words(CHAT_ID, TOPIC_ID).await.unwrap(),
+        items[0].words,
+        "Topic keywords don't match."
+    );
+    assert_eq!(
+        conn.count_contents(CHAT_ID, THREAD_ID, true).await.unwrap(),
+        items.len() as i64,
+        "Topic contents must not be in chat library."
+    );
+    assert_eq!(
+        conn.get_chat_stats(CHAT_ID).await.unwrap().images,
+        items.len() as i64 + 1,
+        "Chat stats must include topic contents."
+    );
+    assert_eq!(
+        conn.get_chat_stats_without(CHAT_ID, TOPIC_ID)
+            .await
+            .unwrap(),
+        chats[0],
+        "Chat stats without topic don't match."
+    );
+    assert_eq!(
+        conn.get_settings(CHAT_ID, TOPIC_ID).await.unwrap(),
+        ChatSettings::new(CHAT_ID, TOPIC_ID),
+        "Topic must have own settings."
+    );
+
+    assert_eq!(conn.get_dialogue_state(CHAT_ID).await.unwrap(), None);
+    conn.set_dialogue_state(CHAT_ID, TEST_WORD1.to_string())
+        .await
+        .unwrap();
+    conn.set_dialogue_state(CHAT_ID, TEST_WORD2.to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.get_dialogue_state(CHAT_ID).await.unwrap(),
+        Some(TEST_WORD2.to_string()),
+        "Saved dialogue state doesn't match."
+    );
+    conn.rm_dialogue_state(CHAT_ID).await.unwrap();
+    assert!(
+        conn.rm_dialogue_state(CHAT_ID).await.is_err(),
+        "Removing missing dialogue must fail."
+    );
+
+    let contributor = Contributor {
+        chat_id: CHAT_ID,
+        user_id: USER_ID,
+        name: TEST_WORD1.to_string(),
+    };
+    assert!(!conn.is_contributor(CHAT_ID, USER_ID).await.unwrap());
+    conn.add_contributor(&contributor).await.unwrap();
+    conn.add_contributor(&contributor).await.unwrap();
+    assert!(conn.is_contributor(CHAT_ID, USER_ID).await.unwrap());
+    assert_eq!(
+        conn.get_contributors(CHAT_ID).await.unwrap(),
+        vec![contributor.clone()],
+        "Contributors don't match."
+    );
+    conn.rm_contributor(CHAT_ID, USER_ID).await.unwrap();
+    assert!(!conn.is_contributor(CHAT_ID, USER_ID).await.unwrap());
+    assert!(
+        conn.rm_contributor(CHAT_ID, USER_ID).await.is_err(),
+        "Removing missing contributor must fail."
+    );
+
+    conn.add_contributor(&contributor).await.unwrap();
+    let words = conn.get_words(CHAT_ID, THREAD_ID).await.unwrap();
+    let stats = conn.get_chat_stats(CHAT_ID).await.unwrap();
+    // Cache words of the new chat before it gets any content
+    conn.get_words(NEW_CHAT_ID, THREAD_ID).await.unwrap();
+    conn.migrate_chat(CHAT_ID, NEW_CHAT_ID).await.unwrap();
+    assert_eq!(
+        conn.get_chat_stats(CHAT_ID).await.unwrap().images,
+        0,
+        "Migrated chat must have no contents."
+    );
+    assert_eq!(
+        conn.get_chat_stats(NEW_CHAT_ID).await.unwrap().images,
+        stats.images,
+        "All contents must be migrated."
+    );
+    assert_eq!(
+        conn.get_words(NEW_CHAT_ID, THREAD_ID)
+            .await
+            .unwrap()
+            .split(',')
+            .collect::<HashSet<&str>>(),
+        words.split(',').collect::<HashSet<&str>>(),
+        "Words of migrated chat don't match."
+    );
+    assert!(conn.is_contributor(NEW_CHAT_ID, USER_ID).await.unwrap());
+    assert!(!conn.is_contributor(CHAT_ID, USER_ID).await.unwrap());
+
+    conn.mark_chat_removed(NEW_CHAT_ID, 100).await.unwrap();
+    conn.restore_chat(NEW_CHAT_ID).await.unwrap();
+    assert!(
+        conn.purge_removed_chats(i64::MAX).await.unwrap().is_empty(),
+        "Restored chat must not be purged."
+    );
+    conn.mark_chat_removed(NEW_CHAT_ID, 100).await.unwrap();
+    conn.mark_chat_removed(NEW_CHAT_ID, 200).await.unwrap();
+    assert!(
+        conn.purge_removed_chats(100).await.unwrap().is_empty(),
+        "Recently removed chat must not be purged."
+    );
+    assert_eq!(
+        conn.purge_removed_chats(150).await.unwrap(),
+        vec![NEW_CHAT_ID],
+        "Chat must be purged by time of the first removal."
+    );
+    assert_eq!(
+        conn.get_chat_stats(NEW_CHAT_ID).await.unwrap().images,
+        0,
+        "Purged chat must have no contents."
+    );
+    assert!(conn
+        .get_words(NEW_CHAT_ID, THREAD_ID)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(!conn.is_contributor(NEW_CHAT_ID, USER_ID).await.unwrap());
 }
@@ -1,20 +1,22 @@
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
-use why_do_you_bot::engine::engine::build_message;
-use why_do_you_bot::models::error::HandlerError;
+use why_do_you_bot::engine::engine::{build_message, render_image};
+use why_do_you_bot::models::attribution::Attribution;
+use why_do_you_bot::models::template::{AttributionPosition, Template};
 use why_do_you_bot::models::v_data::VData;
+use why_do_you_bot::utils::image_utils::normalize_image;
 
 #[tokio::test]
 async fn engine_not_match() {
     let words: Option<String> = Some(String::from("test"));
     let image_handler = async move { return None };
     let audio_handler = async move { return None };
-    match build_message("wow", words, image_handler, audio_handler).await {
-        Ok(_) => {
-            panic!("Can't be Ok(_)")
+    match build_message("wow", words, image_handler, audio_handler, None).await {
+        Ok(v_data) => {
+            assert!(v_data.is_none(), "Can't be Some(_)")
         }
         Err(err) => {
-            assert_eq!(err, HandlerError::empty())
+            panic!("Can't be Err({:?})", err)
         }
     }
 }
@@ -39,9 +41,9 @@ async fn engine_match() {
         return None;
     };
 
-    match build_message("test", words, image_handler, audio_handler).await {
+    match build_message("test", words, image_handler, audio_handler, None).await {
         Ok(v_data) => match v_data {
-            VData::Image(c) => {
+            Some(VData::Image(c)) => {
                 assert!(c.len() > 0, "Image is empty");
                 assert!(
                     _is_call_image_handler.lock().unwrap().deref(),
@@ -52,12 +54,126 @@ async fn engine_match() {
                     "Audio handler was not called"
                 );
             }
-            VData::Video(_) => {
+            Some(VData::Video(_)) => {
                 panic!("Can't be Video(_)")
             }
+            None => {
+                panic!("Can't be None")
+            }
         },
         Err(_) => {
             panic!("Can't be Err(_)")
         }
     }
 }
+
+#[tokio::test]
+async fn engine_render_templates() {
+    for name in Template::builtin_names() {
+        let template = Template::builtin(name).unwrap();
+        let png = render_image("test", None, None, &template).await.unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(
+            image.dimensions(),
+            (template.size, template.size),
+            "Image size of '{}' template does not match.",
+            name
+        );
+        assert_eq!(
+            image.get_pixel(0, 0).0,
+            template.background,
+            "Background of '{}' template does not match.",
+            name
+        );
+    }
+}
+
+#[tokio::test]
+async fn engine_render_attribution() {
+    let image = std::fs::read("assets/pic.jpeg").unwrap();
+    let attribution = Attribution::new(String::from("@test"), None);
+    assert_eq!(attribution.line(), "— @test");
+    for position in [
+        AttributionPosition::BelowText,
+        AttributionPosition::Bottom,
+        AttributionPosition::Top,
+    ] {
+        let mut template = Template::default();
+        template.attribution.position = position;
+        let plain = render_image("test", Some(image.clone()), None, &template)
+            .await
+            .unwrap();
+        let attributed = render_image("test", Some(image.clone()), Some(&attribution), &template)
+            .await
+            .unwrap();
+        assert_ne!(
+            plain, attributed,
+            "Attribution at {:?} is not drawn.",
+            position
+        );
+    }
+}
+
+#[test]
+fn normalize_image_to_jpeg() {
+    let mut png: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(8, 8))
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    let jpeg = normalize_image(png, 16).unwrap();
+    let converted = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+        .unwrap()
+        .to_rgb8();
+    assert_eq!(converted.dimensions(), (8, 8));
+    assert!(
+        converted.pixels().all(|p| p.0.iter().all(|c| *c > 250)),
+        "Transparent pixels must be white."
+    );
+    assert_eq!(
+        normalize_image(jpeg.clone(), 16).unwrap(),
+        jpeg,
+        "JPEG must be kept as is."
+    );
+    assert!(normalize_image(vec![1, 2, 3], 16).is_err());
+
+    let mut gray: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageLuma8(image::GrayImage::new(8, 8))
+        .write_to(
+            &mut std::io::Cursor::new(&mut gray),
+            image::ImageOutputFormat::Jpeg(90),
+        )
+        .unwrap();
+    let converted = normalize_image(gray, 16).unwrap();
+    assert!(
+        image::load_from_memory_with_format(&converted, image::ImageFormat::Jpeg)
+            .unwrap()
+            .as_rgb8()
+            .is_some(),
+        "Grayscale JPEG must be converted to RGB."
+    );
+}
+
+#[test]
+fn normalize_image_downscale() {
+    let mut png: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(40, 20))
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    let jpeg = normalize_image(png, 10).unwrap();
+    let converted = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).unwrap();
+    assert_eq!((converted.width(), converted.height()), (10, 5));
+    let downscaled = normalize_image(jpeg, 4).unwrap();
+    let converted =
+        image::load_from_memory_with_format(&downscaled, image::ImageFormat::Jpeg).unwrap();
+    assert_eq!(
+        (converted.width(), converted.height()),
+        (4, 2),
+        "Large JPEG must be downscaled too."
+    );
+}
@@ -0,0 +1,17 @@
+use why_do_you_bot::utils::shutdown::{active_jobs, cleanup, track_file, wait_jobs, Job};
+
+#[tokio::test]
+async fn shutdown_cleanup() {
+    let job = Job::start();
+    assert_eq!(active_jobs(), 1, "Job was not counted.");
+    drop(job);
+    assert_eq!(active_jobs(), 0, "Job was not finished.");
+    wait_jobs().await;
+
+    let file = std::env::temp_dir().join("why_do_you_bot_shutdown_test.tmp");
+    let path = file.to_str().unwrap();
+    std::fs::write(path, b"temp").unwrap();
+    track_file(path);
+    cleanup();
+    assert!(!file.exists(), "Temp file was not removed.");
+}
@@ -0,0 +1,1190 @@
+//! Bot commands against a mock Bot API server
+//!
+//! Every test starts its own HTTP server answering Bot API requests with canned results
+//! and recording them, feeds scripted updates into the bot handler tree and checks
+//! the recorded requests. Tests share one database and use separate chats.
+
+use axum::body::Bytes;
+use axum::extract::Extension;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::{Json, Router};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use sqlx::migrate::MigrateDatabase;
+#[cfg(feature = "postgres")]
+use sqlx::Postgres as TestDB;
+#[cfg(not(feature = "postgres"))]
+use sqlx::Sqlite as TestDB;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use teloxide::dispatching::update_listeners::webhooks::Options;
+use teloxide::dispatching::Dispatcher;
+use teloxide::error_handlers::LoggingErrorHandler;
+use teloxide::types::{Me, Update};
+use teloxide::Bot;
+use why_do_you_bot::bots::tg::{handler_deps, handler_tree, webhook_listener};
+use why_do_you_bot::models::content_archive::pack_contents;
+use why_do_you_bot::models::content_model::ContentModel;
+use why_do_you_bot::models::db_conn::DBConn;
+use why_do_you_bot::utils::locale::TEXTS;
+use why_do_you_bot::utils::version::VERSION_STRING;
+
+#[cfg(feature = "postgres")]
+const DEFAULT_DB_URL: &str = "postgres://postgres@localhost:5432/why_do_you_bot_tg_test";
+const TEST_DB_URL_KEY: &str = "TEST_DATABASE_URL";
+const BOT_TOKEN: &str = "123456:TEST";
+const BOT_ID: u64 = 123456;
+const BOT_USERNAME: &str = "test_bot";
+const ADMIN_ID: i64 = 1001;
+const MEMBER_ID: i64 = 1002;
+const IMAGE_FILE_ID: &str = "image";
+const AUDIO_FILE_ID: &str = "audio";
+const ARCHIVE_FILE_ID: &str = "archive";
+
+lazy_static! {
+    static ref DB_READY: tokio::sync::Mutex<bool> = tokio::sync::Mutex::new(false);
+}
+
+static MESSAGE_ID: AtomicI32 = AtomicI32::new(1);
+
+/// Bot API request received by mock server
+#[derive(Debug, Clone)]
+struct ApiCall {
+    method: String,
+    /// Text parameters, non-string JSON values are kept serialized
+    params: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    calls: Mutex<Vec<ApiCall>>,
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    /// Error responses returned instead of canned results, queued by method
+    failures: Mutex<HashMap<String, Vec<Value>>>,
+}
+
+/// Mock Bot API server with bot and database connection
+struct TestBot {
+    state: Arc<MockState>,
+    bot: Bot,
+    db_conn: DBConn,
+}
+
+impl TestBot {
+    async fn new() -> Self {
+        // Opt-in features are enabled for all tests, they don't change handling of group messages
+        std::env::set_var("EDITED_MESSAGES", "true");
+        std::env::set_var("CHANNEL_POSTS", "true");
+        std::env::set_var("TOPIC_LIBRARIES", "true");
+        // Inline videos are not encoded in tests
+        std::env::set_var("INLINE_STORAGE_CHAT", "-3000");
+        std::env::set_var("INLINE_DEBOUNCE_MS", "200");
+        std::env::set_var("INLINE_MAX_ENCODES", "0");
+        let state = Arc::new(MockState::default());
+        let app = Router::new()
+            .route("/*path", any(api_handler))
+            .layer(Extension(state.clone()));
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let url = format!("http://{}", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+        Self {
+            state,
+            bot: Bot::new(BOT_TOKEN).set_api_url(url),
+            db_conn: get_db_conn().await,
+        }
+    }
+
+    /// Make file available for `getFile` and downloading
+    fn add_file(&self, file_id: &str, data: Vec<u8>) {
+        self.state
+            .files
+            .lock()
+            .unwrap()
+            .insert(file_id.to_string(), data);
+    }
+
+    /// Answer the next request of method with error
+    fn fail_once(&self, method: &str, error_code: u16, description: &str, parameters: Value) {
+        let mut failure = json!({
+            "ok": false,
+            "error_code": error_code,
+            "description": description
+        });
+        if !parameters.is_null() {
+            failure["parameters"] = parameters;
+        }
+        self.state
+            .failures
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push(failure);
+    }
+
+    /// Run update through handler tree like dispatcher does
+    async fn send(&self, update: Value) {
+        // Update is parsed from text as `serde_json::Value` loses its kind
+        let update: Update = serde_json::from_str(&update.to_string()).unwrap();
+        let mut deps = handler_deps(Some(self.db_conn.clone()));
+        deps.insert(self.bot.clone());
+        deps.insert(get_me());
+        deps.insert(update);
+        let _ = handler_tree().dispatch(deps).await;
+    }
+
+    /// Send text message and get all requests made while handling it
+    async fn send_text(&self, chat_id: i64, user_id: i64, text: &str) -> Vec<ApiCall> {
+        self.send_message(message(chat_id, user_id, text)).await
+    }
+
+    /// Send message and get all requests made while handling it
+    async fn send_message(&self, message: Value) -> Vec<ApiCall> {
+        self.send_update("message", message).await
+    }
+
+    /// Send message as update of kind, e.g. `edited_message`, and get all requests made
+    /// while handling it
+    async fn send_update(&self, kind: &str, message: Value) -> Vec<ApiCall> {
+        self.state.calls.lock().unwrap().clear();
+        self.send(json!({"update_id": 1, kind: message})).await;
+        self.state.calls.lock().unwrap().clone()
+    }
+}
+
+async fn get_db_conn() -> DBConn {
+    let mut ready = DB_READY.lock().await;
+    #[cfg(not(feature = "postgres"))]
+    let default_url = format!(
+        "sqlite:{}",
+        std::env::temp_dir()
+            .join("why_do_you_bot_tg_test.db")
+            .display()
+    );
+    #[cfg(feature = "postgres")]
+    let default_url = DEFAULT_DB_URL.to_string();
+    let db_url = std::env::var(TEST_DB_URL_KEY).unwrap_or(default_url);
+    if !*ready {
+        std::env::set_var("DATABASE_URL", &db_url);
+        if TestDB::database_exists(&db_url).await.unwrap() {
+            TestDB::drop_database(&db_url).await.unwrap()
+        }
+        TestDB::create_database(&db_url).await.unwrap();
+    }
+    let conn = DBConn::new().await.unwrap();
+    if !*ready {
+        conn.migrate().await.unwrap();
+        *ready = true;
+    }
+    conn
+}
+
+async fn api_handler(
+    Extension(state): Extension<Arc<MockState>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    if let Some(file_path) = path.strip_prefix(&format!("file/bot{}/", BOT_TOKEN)) {
+        return match state.files.lock().unwrap().get(file_path) {
+            Some(data) => data.clone().into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        };
+    }
+    // Method names are case-insensitive, teloxide sends them capitalized
+    let method = path
+        .strip_prefix(&format!("bot{}/", BOT_TOKEN))
+        .unwrap_or(path);
+    let method = method[..1].to_lowercase() + &method[1..];
+    let content_type = headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let params = match content_type.split_once("boundary=") {
+        Some((_, boundary)) => parse_multipart(&body, boundary),
+        None => parse_json(&body),
+    };
+    let failure = state
+        .failures
+        .lock()
+        .unwrap()
+        .get_mut(&method)
+        .filter(|failures| !failures.is_empty())
+        .map(|failures| failures.remove(0));
+    let result = api_result(&state, &method, &params);
+    state.calls.lock().unwrap().push(ApiCall { method, params });
+    Json(match (failure, result) {
+        (Some(failure), _) => failure,
+        (None, Some(result)) => json!({"ok": true, "result": result}),
+        (None, None) => json!({"ok": false, "error_code": 400, "description": "Bad Request"}),
+    })
+    .into_response()
+}
+
+/// Canned result of Bot API method
+fn api_result(state: &MockState, method: &str, params: &HashMap<String, String>) -> Option<Value> {
+    let chat_id = params
+        .get("chat_id")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(ADMIN_ID);
+    match method {
+        "getChatAdministrators" => Some(json!([{
+            "status": "creator",
+            "user": user(ADMIN_ID),
+            "is_anonymous": false
+        }])),
+        "getFile" => {
+            let file_id = params.get("file_id")?;
+            let size = state.files.lock().unwrap().get(file_id)?.len();
+            Some(json!({
+                "file_id": file_id,
+                "file_unique_id": file_id,
+                "file_size": size,
+                "file_path": file_id
+            }))
+        }
+        "getMe" => Some(me()),
+        "getUserProfilePhotos" => Some(json!({"total_count": 0, "photos": []})),
+        "getChat" => Some(chat(chat_id)),
+        "copyMessage" => Some(json!({"message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst)})),
+        "sendPhoto" => Some(json!({
+            "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+            "date": 0,
+            "chat": chat(chat_id),
+            "photo": [{"file_id": "photo", "file_unique_id": "photo", "width": 8, "height": 8}]
+        })),
+        method if method.starts_with("send") || method.starts_with("edit") => Some(json!({
+            "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+            "date": 0,
+            "chat": chat(chat_id),
+            "text": "ok"
+        })),
+        _ => Some(json!(true)),
+    }
+}
+
+fn parse_json(body: &[u8]) -> HashMap<String, String> {
+    let value: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+    value
+        .as_object()
+        .map(|object| {
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Text fields of multipart form, files are skipped
+fn parse_multipart(body: &[u8], boundary: &str) -> HashMap<String, String> {
+    let body = String::from_utf8_lossy(body);
+    body.split(&format!("--{}", boundary))
+        .filter_map(|part| {
+            let (headers, value) = part.split_once("\r\n\r\n")?;
+            if headers.contains("filename=") {
+                return None;
+            }
+            let name = headers.split("name=\"").nth(1)?.split('"').next()?;
+            Some((name.to_string(), value.trim_end_matches("\r\n").to_string()))
+        })
+        .collect()
+}
+
+fn me() -> Value {
+    json!({
+        "id": BOT_ID,
+        "is_bot": true,
+        "first_name": "Test",
+        "username": BOT_USERNAME,
+        "can_join_groups": true,
+        "can_read_all_group_messages": true,
+        "supports_inline_queries": false
+    })
+}
+
+fn get_me() -> Me {
+    serde_json::from_value(me()).unwrap()
+}
+
+fn user(id: i64) -> Value {
+    json!({
+        "id": id,
+        "is_bot": false,
+        "first_name": format!("User{}", id),
+        "username": format!("user{}", id),
+        "language_code": "en"
+    })
+}
+
+/// Group for negative ids, private chat otherwise
+fn chat(id: i64) -> Value {
+    if id < 0 {
+        json!({"id": id, "type": "group", "title": "Test"})
+    } else {
+        json!({"id": id, "type": "private", "first_name": format!("User{}", id)})
+    }
+}
+
+fn message(chat_id: i64, user_id: i64, text: &str) -> Value {
+    json!({
+        "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+        "date": 0,
+        "chat": chat(chat_id),
+        "from": user(user_id),
+        "text": text
+    })
+}
+
+/// Post in channel made on behalf of the channel
+fn channel_post(chat_id: i64, text: &str) -> Value {
+    let channel = json!({"id": chat_id, "type": "channel", "title": "Channel"});
+    json!({
+        "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+        "date": 0,
+        "chat": channel,
+        "sender_chat": channel,
+        "text": text
+    })
+}
+
+/// Message moved into topic of forum supergroup
+fn in_topic(mut message: Value, thread_id: i32) -> Value {
+    let chat_id = message["chat"]["id"].clone();
+    message["chat"] =
+        json!({"id": chat_id, "type": "supergroup", "title": "Forum", "is_forum": true});
+    message["message_thread_id"] = json!(thread_id);
+    message["is_topic_message"] = json!(true);
+    message
+}
+
+/// Message edited to have new text or caption
+fn edited(mut message: Value, text: &str) -> Value {
+    let key = if message.get("caption").is_some() {
+        "caption"
+    } else {
+        "text"
+    };
+    message[key] = json!(text);
+    message["edit_date"] = json!(1);
+    message
+}
+
+/// Status of bot in chat changed by admin
+fn my_chat_member(chat_id: i64, status: &str) -> Value {
+    let bot = json!({"id": BOT_ID, "is_bot": true, "first_name": "Test", "username": BOT_USERNAME});
+    json!({
+        "chat": chat(chat_id),
+        "from": user(ADMIN_ID),
+        "date": 0,
+        "old_chat_member": {"user": bot, "status": "member"},
+        "new_chat_member": {"user": bot, "status": status, "until_date": 0}
+    })
+}
+
+/// Message with photo of `IMAGE_FILE_ID` file
+fn photo_message(chat_id: i64, user_id: i64, caption: &str, unique_id: &str) -> Value {
+    json!({
+        "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+        "date": 0,
+        "chat": chat(chat_id),
+        "from": user(user_id),
+        "photo": [{
+            "file_id": IMAGE_FILE_ID,
+            "file_unique_id": unique_id,
+            "width": 8,
+            "height": 8,
+            "file_size": 100
+        }],
+        "caption": caption
+    })
+}
+
+/// Message with audio of `AUDIO_FILE_ID` file
+fn audio_message(chat_id: i64, user_id: i64, duration: u32) -> Value {
+    json!({
+        "message_id": MESSAGE_ID.fetch_add(1, Ordering::SeqCst),
+        "date": 0,
+        "chat": chat(chat_id),
+        "from": user(user_id),
+        "audio": {
+            "file_id": AUDIO_FILE_ID,
+            "file_unique_id": "song",
+            "duration": duration,
+            "file_name": "song.mp3",
+            "mime_type": "audio/mpeg",
+            "file_size": 100
+        }
+    })
+}
+
+/// Inline query of user typed in any chat
+fn inline_query(id: &str, user_id: i64, query: &str) -> Value {
+    json!({
+        "update_id": 1,
+        "inline_query": {
+            "id": id,
+            "from": user(user_id),
+            "query": query,
+            "offset": ""
+        }
+    })
+}
+
+/// Button with data pressed by user under bot message
+fn callback_query(chat_id: i64, user_id: i64, data: &str) -> Value {
+    json!({
+        "id": "query",
+        "from": user(user_id),
+        "message": message(chat_id, BOT_ID as i64, "preview"),
+        "chat_instance": "instance",
+        "data": data
+    })
+}
+
+fn reply_to(mut message: Value, reply: Value) -> Value {
+    message["reply_to_message"] = reply;
+    message
+}
+
+fn png_image() -> Vec<u8> {
+    let mut png: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8))
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    png
+}
+
+/// Photo as Telegram sends it
+fn jpeg_image() -> Vec<u8> {
+    let mut jpeg: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8))
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageOutputFormat::Jpeg(90),
+        )
+        .unwrap();
+    jpeg
+}
+
+fn text(key: &str) -> String {
+    TEXTS.get(key, "en")
+}
+
+/// Texts of sent messages
+fn texts(calls: &[ApiCall]) -> Vec<String> {
+    calls
+        .iter()
+        .filter(|call| call.method == "sendMessage")
+        .filter_map(|call| call.params.get("text").cloned())
+        .collect()
+}
+
+fn methods(calls: &[ApiCall]) -> Vec<&str> {
+    calls.iter().map(|call| call.method.as_str()).collect()
+}
+
+/// Add image with words to chat as admin
+async fn add_image(test: &TestBot, chat_id: i64, words: &str, unique_id: &str) {
+    test.add_file(IMAGE_FILE_ID, png_image());
+    let calls = test
+        .send_message(photo_message(
+            chat_id,
+            ADMIN_ID,
+            &format!("/addimage {}", words),
+            unique_id,
+        ))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_success")]);
+}
+
+#[tokio::test]
+async fn tg_help() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(ADMIN_ID, ADMIN_ID, "/help").await;
+    assert_eq!(texts(&calls), vec![text("tg_private_help_with_db")]);
+    let calls = test
+        .send_text(-2001, MEMBER_ID, &format!("/start@{}", BOT_USERNAME))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_group_help_with_db")]);
+}
+
+#[tokio::test]
+async fn tg_version() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2002, MEMBER_ID, "/version").await;
+    assert_eq!(texts(&calls), vec![VERSION_STRING.to_string()]);
+}
+
+#[tokio::test]
+async fn tg_quote() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2003, MEMBER_ID, "/quote").await;
+    assert_eq!(texts(&calls), vec![text("tg_quote_no_reply")]);
+    let calls = test
+        .send_message(reply_to(
+            message(-2003, MEMBER_ID, "/quote"),
+            message(-2003, ADMIN_ID, "Quote me"),
+        ))
+        .await;
+    assert!(
+        methods(&calls).contains(&"sendPhoto"),
+        "Quote was not sent: {:?}",
+        calls
+    );
+}
+
+#[tokio::test]
+async fn tg_gen() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2004, MEMBER_ID, "/gen Hello").await;
+    assert!(
+        methods(&calls).contains(&"sendPhoto"),
+        "Image was not sent: {:?}",
+        calls
+    );
+    // Attached photo is memed instead of avatar
+    test.add_file(IMAGE_FILE_ID, jpeg_image());
+    let calls = test
+        .send_message(photo_message(MEMBER_ID, MEMBER_ID, "/gen Hello", "gen"))
+        .await;
+    let methods = methods(&calls);
+    assert!(
+        methods.contains(&"getFile") && methods.contains(&"sendPhoto"),
+        "Photo was not memed: {:?}",
+        calls
+    );
+    assert!(!methods.contains(&"getUserProfilePhotos"), "{:?}", calls);
+}
+
+#[tokio::test]
+async fn tg_private_photo() {
+    let test = TestBot::new().await;
+    test.add_file(IMAGE_FILE_ID, jpeg_image());
+    for caption in ["Hello", "/gen Hello"] {
+        let calls = test
+            .send_message(photo_message(MEMBER_ID, MEMBER_ID, caption, "private"))
+            .await;
+        let methods = methods(&calls);
+        assert!(
+            methods.contains(&"getFile") && methods.contains(&"sendPhoto"),
+            "Photo with caption {} was not memed: {:?}",
+            caption,
+            calls
+        );
+        assert!(!methods.contains(&"getUserProfilePhotos"), "{:?}", calls);
+    }
+    let mut photo = photo_message(MEMBER_ID, MEMBER_ID, "", "private");
+    photo.as_object_mut().unwrap().remove("caption");
+    let calls = test.send_message(photo).await;
+    assert!(
+        calls.is_empty(),
+        "Photo without caption was memed: {:?}",
+        calls
+    );
+}
+
+#[tokio::test]
+async fn tg_list_contents() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2005, MEMBER_ID, "/listimage").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+    let calls = test.send_text(-2005, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+
+    add_image(&test, -2005, "qqq", "list").await;
+    let calls = test.send_text(-2005, MEMBER_ID, "/listimage").await;
+    assert_eq!(methods(&calls), vec!["sendPhoto"]);
+    assert!(calls[0].params["caption"].contains("qqq"));
+    let calls = test.send_text(-2005, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![String::from("qqq")]);
+
+    test.add_file(AUDIO_FILE_ID, b"ID3 audio".to_vec());
+    let calls = test
+        .send_message(reply_to(
+            message(-2005, ADMIN_ID, "/addaudio www"),
+            audio_message(-2005, ADMIN_ID, 10),
+        ))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_audio_add_success")]);
+    let calls = test.send_text(-2005, MEMBER_ID, "/listaudio").await;
+    assert_eq!(methods(&calls), vec!["sendAudio"]);
+}
+
+#[tokio::test]
+async fn tg_add_content_errors() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2006, ADMIN_ID, "/addimage qqq").await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_format_error")]);
+    let calls = test.send_text(-2006, ADMIN_ID, "/addimage ???").await;
+    assert_eq!(texts(&calls), vec![text("tg_keyword_error")]);
+
+    test.add_file(IMAGE_FILE_ID, vec![1, 2, 3]);
+    let calls = test
+        .send_message(photo_message(-2006, ADMIN_ID, "/addimage qqq", "broken"))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_format_invalid")]);
+
+    let calls = test
+        .send_message(reply_to(
+            message(-2006, ADMIN_ID, "/addaudio qqq"),
+            audio_message(-2006, ADMIN_ID, 100000),
+        ))
+        .await;
+    assert_eq!(texts(&calls).len(), 1);
+    assert!(texts(&calls)[0].starts_with(&text("tg_limit_audio_duration")));
+}
+
+#[tokio::test]
+async fn tg_add_flow() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2007, ADMIN_ID, "/addaudio").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_ask_audio")]);
+    let calls = test.send_text(-2007, MEMBER_ID, "/cancel").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_nothing_to_cancel")]);
+    let calls = test.send_text(-2007, ADMIN_ID, "not an audio").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_ask_audio")]);
+    let calls = test.send_text(-2007, ADMIN_ID, "/cancel").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_cancelled")]);
+}
+
+#[tokio::test]
+async fn tg_add_flow_users() {
+    let test = TestBot::new().await;
+    test.add_file(IMAGE_FILE_ID, png_image());
+    test.send_message(reply_to(
+        message(-2019, ADMIN_ID, "/grant"),
+        message(-2019, MEMBER_ID, "Hi"),
+    ))
+    .await;
+    let calls = test.send_text(-2019, MEMBER_ID, "/addimage").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_ask_image")]);
+    let calls = test.send_text(-2019, ADMIN_ID, "/addimage").await;
+    assert_eq!(
+        texts(&calls),
+        vec![text("tg_flow_busy")],
+        "Flow of other user was replaced."
+    );
+    let calls = test
+        .send_message(photo_message(-2019, MEMBER_ID, "", "flow"))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_ask_words")]);
+    let calls = test.send_text(-2019, MEMBER_ID, "qqq").await;
+    assert_eq!(
+        methods(&calls),
+        vec!["copyMessage"],
+        "Preview is not media."
+    );
+    assert_eq!(
+        calls[0].params.get("caption").map(String::as_str),
+        Some("flow.jpg - qqq")
+    );
+
+    test.send_text(-2019, ADMIN_ID, &format!("/revoke {}", MEMBER_ID))
+        .await;
+    let calls = test
+        .send_update(
+            "callback_query",
+            callback_query(-2019, MEMBER_ID, "flow:save"),
+        )
+        .await;
+    assert_eq!(
+        calls[0].params.get("text"),
+        Some(&text("tg_not_permitted")),
+        "Demoted user saved content."
+    );
+    assert!(texts(&calls).is_empty());
+
+    let calls = test.send_text(-2019, ADMIN_ID, "/addimage").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_ask_image")]);
+    let calls = test.send_text(-2019, MEMBER_ID, "/cancel").await;
+    assert_eq!(texts(&calls), vec![text("tg_flow_nothing_to_cancel")]);
+    test.send_message(photo_message(-2019, ADMIN_ID, "", "flow"))
+        .await;
+    test.send_text(-2019, ADMIN_ID, "qqq").await;
+    let calls = test
+        .send_update(
+            "callback_query",
+            callback_query(-2019, ADMIN_ID, "flow:save"),
+        )
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_success")]);
+
+    test.send_message(reply_to(
+        message(-2019, ADMIN_ID, "/grant"),
+        message(-2019, MEMBER_ID, "Hi"),
+    ))
+    .await;
+    test.send_text(-2019, MEMBER_ID, "/addaudio").await;
+    let calls = test.send_text(-2019, ADMIN_ID, "/cancel").await;
+    assert_eq!(
+        texts(&calls),
+        vec![text("tg_flow_cancelled")],
+        "Admin can't cancel flow of other user."
+    );
+}
+
+#[tokio::test]
+async fn tg_inline() {
+    let test = TestBot::new().await;
+    test.send(inline_query("short", 3001, "hi")).await;
+    assert!(
+        test.state.calls.lock().unwrap().is_empty(),
+        "Short query was rendered."
+    );
+
+    tokio::join!(
+        test.send(inline_query("typing", 3002, "hello")),
+        test.send(inline_query("typed", 3002, "hello world")),
+    );
+    let calls = test.state.calls.lock().unwrap().clone();
+    let answers: Vec<&str> = calls
+        .iter()
+        .filter(|call| call.method == "answerInlineQuery")
+        .filter_map(|call| call.params.get("inline_query_id").map(String::as_str))
+        .collect();
+    assert_eq!(answers, vec!["typed"], "Query was not debounced.");
+}
+
+#[tokio::test]
+async fn tg_edit_and_remove_content() {
+    let test = TestBot::new().await;
+    add_image(&test, -2008, "qqq", "edit").await;
+    let calls = test
+        .send_text(-2008, ADMIN_ID, "/editimage edit.jpg www")
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_done_msg")]);
+    let calls = test.send_text(-2008, ADMIN_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![String::from("www")]);
+
+    let calls = test.send_text(-2008, MEMBER_ID, "/rmimage edit.jpg").await;
+    assert_eq!(texts(&calls), vec![text("tg_not_permitted")]);
+    let calls = test.send_text(-2008, ADMIN_ID, "/rmimage edit.jpg").await;
+    assert_eq!(texts(&calls), vec![text("tg_rm_content_success")]);
+    let calls = test.send_text(-2008, ADMIN_ID, "/rmaudio edit.jpg").await;
+    assert_eq!(texts(&calls), vec![text("tg_rm_content_error")]);
+    let calls = test.send_text(-2008, ADMIN_ID, "/editaudio").await;
+    assert_eq!(texts(&calls), vec![text("tg_invalid_arguments")]);
+}
+
+#[tokio::test]
+async fn tg_export_import() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2009, ADMIN_ID, "/export").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+    add_image(&test, -2009, "qqq", "export").await;
+    let calls = test.send_text(-2009, ADMIN_ID, "/export").await;
+    assert_eq!(methods(&calls), vec!["sendDocument"]);
+
+    let items = vec![ContentModel::from(
+        -2009,
+        true,
+        String::from("www"),
+        String::from("imported.jpg"),
+        png_image(),
+    )];
+    test.add_file(ARCHIVE_FILE_ID, pack_contents(&items).unwrap());
+    let mut import = message(-2009, ADMIN_ID, "");
+    import["caption"] = json!("/import");
+    import["document"] = json!({
+        "file_id": ARCHIVE_FILE_ID,
+        "file_unique_id": ARCHIVE_FILE_ID,
+        "file_name": "contents.zip",
+        "mime_type": "application/zip",
+        "file_size": 100
+    });
+    import.as_object_mut().unwrap().remove("text");
+    let calls = test.send_message(import).await;
+    assert_eq!(
+        texts(&calls),
+        vec![format!("{} 1", text("tg_import_success"))]
+    );
+}
+
+#[tokio::test]
+async fn tg_attribution() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2010, ADMIN_ID, "/attribution on").await;
+    assert_eq!(texts(&calls), vec![text("tg_attribution_on")]);
+    let calls = test.send_text(-2010, ADMIN_ID, "/attribution").await;
+    assert_eq!(texts(&calls), vec![text("tg_attribution_off")]);
+}
+
+#[tokio::test]
+async fn tg_roles() {
+    let test = TestBot::new().await;
+    test.add_file(IMAGE_FILE_ID, png_image());
+    let calls = test
+        .send_message(photo_message(-2011, MEMBER_ID, "/addimage qqq", "role"))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_not_permitted")]);
+    let calls = test.send_text(-2011, MEMBER_ID, "/grant").await;
+    assert_eq!(texts(&calls), vec![text("tg_not_permitted")]);
+
+    let calls = test.send_text(-2011, ADMIN_ID, "/grant").await;
+    assert_eq!(texts(&calls), vec![text("tg_role_no_target")]);
+    let calls = test
+        .send_message(reply_to(
+            message(-2011, ADMIN_ID, "/grant"),
+            message(-2011, MEMBER_ID, "Hi"),
+        ))
+        .await;
+    assert_eq!(
+        texts(&calls),
+        vec![format!("{} User{}", text("tg_grant_success"), MEMBER_ID)]
+    );
+    let calls = test
+        .send_message(photo_message(-2011, MEMBER_ID, "/addimage qqq", "role"))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_success")]);
+    let calls = test.send_text(-2011, ADMIN_ID, "/contributors").await;
+    assert_eq!(
+        texts(&calls),
+        vec![format!("User{} - {}", MEMBER_ID, MEMBER_ID)]
+    );
+
+    let calls = test
+        .send_text(-2011, ADMIN_ID, &format!("/revoke {}", MEMBER_ID))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_revoke_success")]);
+    let calls = test
+        .send_text(-2011, ADMIN_ID, &format!("/revoke {}", MEMBER_ID))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_revoke_error")]);
+    let calls = test.send_text(-2011, ADMIN_ID, "/contributors").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+}
+
+#[tokio::test]
+async fn tg_edited_message() {
+    let test = TestBot::new().await;
+    add_image(&test, -2012, "qqq", "edited").await;
+    let original = message(-2012, MEMBER_ID, "Hello");
+    let calls = test.send_message(original.clone()).await;
+    assert!(calls.is_empty(), "Unexpected requests: {:?}", calls);
+    let calls = test
+        .send_update("edited_message", edited(original.clone(), "Hello qqq"))
+        .await;
+    assert_eq!(methods(&calls), vec!["sendPhoto"]);
+    let calls = test
+        .send_update("edited_message", edited(original, "Hello qqq again"))
+        .await;
+    assert!(calls.is_empty(), "Message was memed twice: {:?}", calls);
+
+    let command = message(-2012, ADMIN_ID, "/listwords");
+    test.send_message(command.clone()).await;
+    let calls = test
+        .send_update("edited_message", edited(command, "/listwords qqq"))
+        .await;
+    assert!(calls.is_empty(), "Edited command was run: {:?}", calls);
+}
+
+#[tokio::test]
+async fn tg_channel_post() {
+    let test = TestBot::new().await;
+    test.add_file(IMAGE_FILE_ID, png_image());
+    let mut add = photo_message(-2013, ADMIN_ID, "/addimage qqq", "channel");
+    add["chat"] = json!({"id": -2013, "type": "channel", "title": "Channel"});
+    add["sender_chat"] = add["chat"].clone();
+    add.as_object_mut().unwrap().remove("from");
+    let calls = test.send_update("channel_post", add).await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_success")]);
+
+    let post = channel_post(-2013, "Hello qqq");
+    let calls = test.send_update("channel_post", post.clone()).await;
+    assert_eq!(methods(&calls), vec!["sendPhoto"]);
+    let calls = test
+        .send_update("edited_channel_post", edited(post, "Hello qqq!"))
+        .await;
+    assert!(calls.is_empty(), "Post was memed twice: {:?}", calls);
+
+    for command in ["/listimage", "/addimage"] {
+        let calls = test
+            .send_update("channel_post", channel_post(-2013, command))
+            .await;
+        assert_eq!(texts(&calls), vec![text("tg_channel_unsupported")]);
+    }
+
+    // Library of the channel is separate from other chats
+    let calls = test.send_text(-2014, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+}
+
+#[tokio::test]
+async fn tg_forum_topics() {
+    let test = TestBot::new().await;
+    test.add_file(IMAGE_FILE_ID, png_image());
+    let calls = test
+        .send_message(in_topic(
+            photo_message(-2015, ADMIN_ID, "/addimage qqq", "topic"),
+            5,
+        ))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_image_add_success")]);
+
+    let calls = test
+        .send_message(in_topic(message(-2015, MEMBER_ID, "Hello qqq"), 5))
+        .await;
+    assert_eq!(methods(&calls), vec!["sendPhoto"]);
+    assert_eq!(
+        calls[0].params.get("message_thread_id").map(String::as_str),
+        Some("5"),
+        "Meme was not posted into topic."
+    );
+
+    // Other topics and General topic have own libraries
+    let calls = test
+        .send_message(in_topic(message(-2015, MEMBER_ID, "/listwords"), 6))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+    let calls = test.send_text(-2015, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+    let calls = test
+        .send_message(in_topic(message(-2015, MEMBER_ID, "/listwords"), 5))
+        .await;
+    assert_eq!(texts(&calls), vec![String::from("qqq")]);
+
+    let calls = test
+        .send_message(in_topic(message(-2015, ADMIN_ID, "/triggers off"), 5))
+        .await;
+    assert_eq!(texts(&calls), vec![text("tg_triggers_off")]);
+    let calls = test
+        .send_message(in_topic(message(-2015, MEMBER_ID, "Hello qqq"), 5))
+        .await;
+    assert!(calls.is_empty(), "Topic without triggers was memed.");
+    let calls = test
+        .send_message(in_topic(message(-2015, ADMIN_ID, "/triggers"), 6))
+        .await;
+    assert_eq!(
+        texts(&calls),
+        vec![text("tg_triggers_off")],
+        "Triggers of other topic were changed."
+    );
+}
+
+#[tokio::test]
+async fn tg_chat_lifecycle() {
+    let test = TestBot::new().await;
+    add_image(&test, -2016, "qqq", "lifecycle").await;
+
+    // Group is upgraded while meme is sent, it is resent to the supergroup with the library
+    test.fail_once(
+        "sendPhoto",
+        400,
+        "Bad Request: group chat was upgraded to a supergroup chat",
+        json!({"migrate_to_chat_id": -1002016}),
+    );
+    let calls = test.send_text(-2016, MEMBER_ID, "Hello qqq").await;
+    assert_eq!(methods(&calls), vec!["sendPhoto", "sendPhoto"]);
+    assert_eq!(
+        calls[1].params.get("chat_id").map(String::as_str),
+        Some("-1002016"),
+        "Meme was not resent to the supergroup."
+    );
+    let calls = test.send_text(-1002016, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![String::from("qqq")]);
+    let calls = test.send_text(-2016, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+
+    // Migration service message moves library too
+    let mut service = message(-1002017, ADMIN_ID, "");
+    service.as_object_mut().unwrap().remove("text");
+    service["migrate_from_chat_id"] = json!(-1002016);
+    test.send_message(service).await;
+    let calls = test.send_text(-1002017, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![String::from("qqq")]);
+
+    test.fail_once(
+        "sendPhoto",
+        429,
+        "Too Many Requests: retry after 1",
+        json!({"retry_after": 1}),
+    );
+    let calls = test.send_text(-1002017, MEMBER_ID, "Hello qqq").await;
+    assert_eq!(
+        methods(&calls),
+        vec!["sendPhoto", "sendPhoto"],
+        "Throttled meme was not resent."
+    );
+
+    // Removed chat is kept until purged, unless bot is back
+    test.send_update("my_chat_member", my_chat_member(-1002017, "kicked"))
+        .await;
+    test.send_update("my_chat_member", my_chat_member(-1002017, "member"))
+        .await;
+    assert!(test
+        .db_conn
+        .purge_removed_chats(i64::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+    test.fail_once(
+        "sendPhoto",
+        403,
+        "Forbidden: bot was kicked from the group chat",
+        Value::Null,
+    );
+    test.send_text(-1002017, MEMBER_ID, "Hello qqq").await;
+    assert_eq!(
+        test.db_conn.purge_removed_chats(i64::MAX).await.unwrap(),
+        vec![-1002017]
+    );
+    let calls = test.send_text(-1002017, MEMBER_ID, "/listwords").await;
+    assert_eq!(texts(&calls), vec![text("tg_empty_list_message")]);
+}
+
+#[tokio::test]
+async fn tg_error_replies() {
+    let test = TestBot::new().await;
+    let calls = test.send_text(-2018, ADMIN_ID, "/errors").await;
+    assert_eq!(
+        texts(&calls),
+        vec![format!("{} brief", text("tg_errors_usage"))]
+    );
+    let calls = test.send_text(-2018, ADMIN_ID, "/errors detailed").await;
+    assert_eq!(
+        texts(&calls),
+        vec![format!("{} detailed", text("tg_errors_set"))]
+    );
+    add_image(&test, -2018, "qqq", "errors").await;
+
+    // Image is only sent when video could not be encoded
+    let calls = test.send_text(-2018, MEMBER_ID, "Hello qqq").await;
+    let meme_method = calls[0].method.clone();
+    let caption = calls[0].params.get("caption").cloned();
+    match meme_method.as_str() {
+        "sendPhoto" => assert_eq!(caption, Some(text("tg_video_fallback"))),
+        _ => assert_eq!(caption, None),
+    }
+
+    // Flood control outlasting retries is reported, reason is shown to admins only
+    let rate_limit = |test: &TestBot| {
+        for _ in 0..4 {
+            test.fail_once(
+                &meme_method,
+                429,
+                "Too Many Requests: retry after 0",
+                json!({"retry_after": 0}),
+            );
+        }
+    };
+    rate_limit(&test);
+    let calls = test.send_text(-2018, MEMBER_ID, "Hello qqq").await;
+    assert_eq!(texts(&calls), vec![text("tg_rate_limited")]);
+    rate_limit(&test);
+    let calls = test.send_text(-2018, ADMIN_ID, "Hello qqq").await;
+    let reply = texts(&calls);
+    assert!(
+        reply.len() == 1 && reply[0].starts_with(&format!("{}\n", text("tg_rate_limited"))),
+        "Admin got no reason: {:?}",
+        reply
+    );
+
+    test.send_text(-2018, ADMIN_ID, "/errors silent").await;
+    rate_limit(&test);
+    let calls = test.send_text(-2018, MEMBER_ID, "Hello qqq").await;
+    assert!(
+        texts(&calls).is_empty(),
+        "Silent chat got reply: {:?}",
+        calls
+    );
+}
+
+#[tokio::test]
+async fn tg_webhook() {
+    let test = TestBot::new().await;
+    // Free port for webhook server
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let options = Options::new(address, "https://example.com/webhook".parse().unwrap())
+        .secret_token(String::from("secret"));
+    let listener = webhook_listener(test.bot.clone(), options).await;
+    let mut dispatcher = Dispatcher::builder(test.bot.clone(), handler_tree())
+        .dependencies(handler_deps(Some(test.db_conn.clone())))
+        .build();
+    let shutdown = dispatcher.shutdown_token();
+    let dispatching = tokio::spawn(async move {
+        dispatcher
+            .dispatch_with_listener(listener, LoggingErrorHandler::new())
+            .await
+    });
+    let url = format!("http://{}/webhook", address);
+    let client = reqwest::Client::new();
+    let post = |secret: Option<&str>| {
+        let message_id = MESSAGE_ID.fetch_add(1, Ordering::SeqCst);
+        let update = json!({
+            "update_id": message_id,
+            "message": message(-2020, MEMBER_ID, "/version")
+        });
+        let mut request = client.post(&url).json(&update);
+        if let Some(secret) = secret {
+            request = request.header("X-Telegram-Bot-Api-Secret-Token", secret);
+        }
+        request.send()
+    };
+    let sent = |state: &MockState| {
+        state
+            .calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.method == "sendMessage")
+            .count()
+    };
+    let set_webhook = test
+        .state
+        .calls
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|call| call.method == "setWebhook")
+        .cloned()
+        .expect("Webhook was not set");
+    assert_eq!(
+        set_webhook.params.get("secret_token").map(String::as_str),
+        Some("secret")
+    );
+    // Server is spawned by listener and binds the address in background
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(address).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    for secret in [None, Some("wrong")] {
+        let response = post(secret).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+    let response = post(Some("secret")).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    for _ in 0..50 {
+        if sent(&test.state) > 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(sent(&test.state), 1, "Only update with secret is handled");
+    shutdown.shutdown().unwrap().await;
+    dispatching.await.unwrap();
+    assert!(
+        methods(&test.state.calls.lock().unwrap()).contains(&"deleteWebhook"),
+        "Webhook was not removed"
+    );
+}